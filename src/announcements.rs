@@ -0,0 +1,184 @@
+use chrono::{DateTime, Duration, Local, NaiveDate, TimeZone};
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result,
+};
+use colored::Colorize;
+use reqwest::Method;
+
+use crate::{
+    cache,
+    canvas::{find_course, load_courses},
+    canvas_api::{CanvasCourse, DiscussionTopic},
+    canvas_url, colorize, config, fetch_paginated, format_datetime, format_duration_full, html,
+    send_empty,
+};
+
+/// How far back to look when `--since` is not given, which is also Canvas's own default.
+const DEFAULT_DAYS: i64 = 14;
+
+/// Parses `--since`, given either as a date or as a number of days ago.
+pub fn parse_since(s: &str) -> Result<DateTime<Local>, String> {
+    if let Ok(days) = s.trim_end_matches('d').parse::<i64>() {
+        return Ok(Local::now() - Duration::days(days));
+    }
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .ok()
+        .and_then(|x| Local.from_local_datetime(&x.and_hms_opt(0, 0, 0)?).single())
+        .ok_or_else(|| {
+            format!(
+                "expected a date like 2024-09-01 or a number of days, got `{}`",
+                s
+            )
+        })
+}
+
+fn announcements_url(courses: &[&CanvasCourse], since: DateTime<Local>) -> String {
+    let mut url = format!(
+        "/api/v1/announcements?per_page=100&start_date={}",
+        since.format("%Y-%m-%d")
+    );
+    for course in courses {
+        url += &format!("&context_codes[]=course_{}", course.id);
+    }
+    url
+}
+
+/// Loads the announcements posted since `since` in the given courses, newest first.
+pub async fn load_announcements(
+    config: &config::Config,
+    courses: &[&CanvasCourse],
+    since: DateTime<Local>,
+) -> Result<Vec<DiscussionTopic>> {
+    if courses.is_empty() {
+        return Ok(vec![]);
+    }
+    let mut announcements: Vec<DiscussionTopic> =
+        fetch_paginated(config, &announcements_url(courses, since)).await?;
+    announcements.sort_by_key(|x| std::cmp::Reverse(x.posted_at));
+    Ok(announcements)
+}
+
+fn is_unread(announcement: &DiscussionTopic) -> bool {
+    announcement.read_state.as_deref() == Some("unread")
+}
+
+/// The number of recent unread announcements across all active courses.
+pub async fn count_unread(config: &config::Config) -> Result<usize> {
+    let courses = load_courses(config).await?;
+    let courses: Vec<_> = courses.iter().collect();
+    let since = Local::now() - Duration::days(DEFAULT_DAYS);
+    let announcements = load_announcements(config, &courses, since).await?;
+    Ok(announcements.iter().filter(|x| is_unread(x)).count())
+}
+
+fn course_id(announcement: &DiscussionTopic) -> Option<i64> {
+    announcement
+        .context_code
+        .as_deref()?
+        .strip_prefix("course_")?
+        .parse()
+        .ok()
+}
+
+pub async fn run_announcements(
+    config: &config::Config,
+    course: Option<String>,
+    since: Option<DateTime<Local>>,
+    unread: bool,
+    keep_unread: bool,
+) -> Result<()> {
+    let all_courses = load_courses(config).await?;
+    let courses = match &course {
+        Some(query) => vec![find_course(&all_courses, query)?],
+        None => all_courses.iter().collect(),
+    };
+    let since = since.unwrap_or_else(|| Local::now() - Duration::days(DEFAULT_DAYS));
+
+    let mut announcements = load_announcements(config, &courses, since).await?;
+    if unread {
+        announcements.retain(is_unread);
+    }
+
+    if announcements.is_empty() {
+        println!(
+            "{}",
+            if unread {
+                "No unread announcements."
+            } else {
+                "No announcements."
+            }
+        );
+        return Ok(());
+    }
+
+    let now = Local::now();
+    let mut marked = 0;
+    for announcement in &announcements {
+        let course = course_id(announcement).and_then(|id| {
+            courses
+                .iter()
+                .position(|x| x.id == id)
+                .map(|i| (i, courses[i]))
+        });
+        let course_name = match course {
+            Some((i, course)) => colorize(i, &course.name),
+            None => "Unknown course".into(),
+        };
+
+        let mut heading = format!("{} - {}", announcement.title.trim().bold(), course_name);
+        if is_unread(announcement) {
+            heading += &" (new)".green().bold().to_string();
+        }
+        println!("{}", heading);
+        let mut byline = vec![];
+        if let Some(author) = &announcement.user_name {
+            byline.push(author.clone());
+        }
+        if let Some(posted_at) = announcement.posted_at {
+            byline.push(format!(
+                "posted {} ({})",
+                format_datetime(posted_at),
+                format_duration_full(now, posted_at)
+            ));
+        }
+        if !byline.is_empty() {
+            println!("{}", byline.join(", ").bright_black());
+        }
+        if let Some(message) = announcement.message.as_deref() {
+            println!();
+            println!("{}", html::render(message));
+        }
+        if let Some(url) = &announcement.html_url {
+            println!();
+            println!("{}", url);
+        }
+        println!();
+
+        if is_unread(announcement) && !keep_unread {
+            if let Some((_, course)) = course {
+                send_empty(
+                    config,
+                    Method::PUT,
+                    &format!(
+                        "/api/v1/courses/{}/discussion_topics/{}/read",
+                        course.id, announcement.id
+                    ),
+                )
+                .await
+                .wrap_err_with(|| eyre!("Unable to mark {} as read", announcement.title.trim()))?;
+                marked += 1;
+            }
+        }
+    }
+
+    // Read states are part of the cached response, which would otherwise still say unread.
+    if marked > 0 {
+        cache::invalidate(&canvas_url(config, &announcements_url(&courses, since))).await;
+        let all: Vec<_> = all_courses.iter().collect();
+        let since = Local::now() - Duration::days(DEFAULT_DAYS);
+        cache::invalidate(&canvas_url(config, &announcements_url(&all, since))).await;
+    }
+
+    Ok(())
+}
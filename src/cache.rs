@@ -0,0 +1,325 @@
+#[cfg(unix)]
+use std::{fs::Permissions, os::unix::fs::PermissionsExt};
+use std::{path::PathBuf, sync::Mutex};
+
+use chrono::{DateTime, Local};
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result, Section,
+};
+use lazy_static::lazy_static;
+use reqwest::{
+    header::{HeaderMap, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, LINK},
+    RequestBuilder, StatusCode, Url,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{fs, io::AsyncWriteExt};
+
+use crate::{config, retry};
+
+/// A response body stored on disk, along with what is needed to revalidate it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub link: Option<String>,
+    pub fetched_at: DateTime<Local>,
+    pub body: String,
+}
+
+lazy_static! {
+    static ref OLDEST_SERVED: Mutex<Option<DateTime<Local>>> = Mutex::new(None);
+}
+
+pub fn cache_dir() -> PathBuf {
+    // Tests talk to throwaway mock servers, whose responses should not end up in the real cache.
+    if cfg!(test) {
+        return std::env::temp_dir().join(format!("canvas-test-{}", std::process::id()));
+    }
+    dirs::cache_dir().unwrap().join("canvas")
+}
+
+/// FNV-1a, which unlike `DefaultHasher` is guaranteed to be the same across runs and builds.
+pub fn stable_hash(s: &str) -> u64 {
+    s.bytes().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn entry_path(url: &Url) -> PathBuf {
+    cache_dir().join(format!("{:016x}.json", stable_hash(url.as_str())))
+}
+
+/// The fetch time of the oldest entry that was served without contacting the server.
+pub fn served_as_of() -> Option<DateTime<Local>> {
+    *OLDEST_SERVED.lock().unwrap()
+}
+
+fn mark_served(entry: &Entry) {
+    let mut oldest = OLDEST_SERVED.lock().unwrap();
+    if oldest.is_none_or(|x| entry.fetched_at < x) {
+        *oldest = Some(entry.fetched_at);
+    }
+}
+
+async fn load(url: &Url) -> Option<Entry> {
+    let data = fs::read(entry_path(url)).await.ok()?;
+    serde_json::from_slice(&data).ok()
+}
+
+/// Entries hold grades, messages and pages fetched with the user's credentials, so only the user
+/// should be able to read them.
+async fn store(url: &Url, entry: &Entry) -> Result<()> {
+    let dir = cache_dir();
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    builder.mode(0o700);
+    builder.create(&dir).await?;
+    #[cfg(unix)]
+    fs::set_permissions(&dir, Permissions::from_mode(0o700)).await?;
+
+    let path = entry_path(url);
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(&path).await?;
+    #[cfg(unix)]
+    file.set_permissions(Permissions::from_mode(0o600)).await?;
+    file.write_all(&serde_json::to_vec(entry)?).await?;
+    Ok(())
+}
+
+/// Forgets a cached response, so the next fetch goes to the server.
+pub async fn invalidate(url: &Url) {
+    let _ = fs::remove_file(entry_path(url)).await;
+}
+
+fn header(headers: &HeaderMap, name: impl reqwest::header::AsHeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|x| x.to_str().ok())
+        .map(|x| x.to_string())
+}
+
+/// Fetches `url` through the on-disk cache.
+///
+/// Entries younger than `cache_ttl_secs` are returned as is, older ones are revalidated with a
+/// conditional request, and in offline mode the cache is the only source.
+pub async fn get(
+    config: &config::Config,
+    url: &Url,
+    request: impl Fn() -> RequestBuilder,
+) -> Result<Entry> {
+    get_within(config, url, config.cache_ttl_secs, request).await
+}
+
+/// Like [`get`], but always checks with the server outside of offline mode, for reads that
+/// decide whether a change is allowed.
+pub async fn get_fresh(
+    config: &config::Config,
+    url: &Url,
+    request: impl Fn() -> RequestBuilder,
+) -> Result<Entry> {
+    get_within(config, url, 0, request).await
+}
+
+async fn get_within(
+    config: &config::Config,
+    url: &Url,
+    max_age_secs: u64,
+    request: impl Fn() -> RequestBuilder,
+) -> Result<Entry> {
+    let cached = load(url).await;
+
+    if config.offline {
+        let entry = cached
+            .ok_or_else(|| eyre!("No cached copy of {}", url))
+            .suggestion("Run the command once without --offline to fill the cache")?;
+        mark_served(&entry);
+        return Ok(entry);
+    }
+
+    if let Some(entry) = &cached {
+        if (Local::now() - entry.fetched_at).num_seconds() < max_age_secs as i64 {
+            mark_served(entry);
+            return Ok(entry.clone());
+        }
+    }
+
+    let response = retry::send(config, || {
+        let mut request = request();
+        if let Some(entry) = &cached {
+            if let Some(etag) = &entry.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &entry.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        request
+    })
+    .await?;
+
+    let entry = match cached {
+        Some(entry) if response.status() == StatusCode::NOT_MODIFIED => Entry {
+            fetched_at: Local::now(),
+            ..entry
+        },
+        _ => {
+            let headers = response.headers().clone();
+            let link: Vec<_> = headers
+                .get_all(LINK)
+                .iter()
+                .filter_map(|x| x.to_str().ok())
+                .collect();
+            Entry {
+                url: url.to_string(),
+                etag: header(&headers, ETAG),
+                last_modified: header(&headers, LAST_MODIFIED),
+                link: if link.is_empty() {
+                    None
+                } else {
+                    Some(link.join(", "))
+                },
+                fetched_at: Local::now(),
+                body: response
+                    .text()
+                    .await
+                    .wrap_err("Failed to read data from server")?,
+            }
+        }
+    };
+
+    // A cache that cannot be written should not prevent the command from working.
+    let _ = store(url, &entry).await;
+
+    Ok(entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        matchers::{header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+    use crate::{config::test_config, CLIENT};
+
+    async fn get_page(config: &config::Config, url: &Url) -> Result<Entry> {
+        get(config, url, || CLIENT.get(url.clone())).await
+    }
+
+    /// Mock servers are pooled and reuse their ports, so every test needs its own path to keep
+    /// from seeing another test's entries.
+    fn page_url(server: &MockServer, page: &str) -> Url {
+        Url::parse(&format!("{}{}", server.uri(), page)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn fresh_entries_are_served_from_disk() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/fresh"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("grades"))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let mut config = test_config(&server.uri());
+        config.cache_ttl_secs = 3600;
+        let url = page_url(&server, "/fresh");
+
+        assert_eq!(get_page(&config, &url).await.unwrap().body, "grades");
+        assert_eq!(get_page(&config, &url).await.unwrap().body, "grades");
+
+        #[cfg(unix)]
+        {
+            let mode = |path: PathBuf| std::fs::metadata(path).unwrap().permissions().mode();
+            assert_eq!(mode(entry_path(&url)) & 0o777, 0o600);
+            assert_eq!(mode(cache_dir()) & 0o777, 0o700);
+        }
+    }
+
+    #[tokio::test]
+    async fn fresh_reads_skip_the_ttl() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/refreshed"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("attempt 1"))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/refreshed"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("attempt 2"))
+            .mount(&server)
+            .await;
+        let mut config = test_config(&server.uri());
+        config.cache_ttl_secs = 3600;
+        let url = page_url(&server, "/refreshed");
+
+        assert_eq!(get_page(&config, &url).await.unwrap().body, "attempt 1");
+        let entry = get_fresh(&config, &url, || CLIENT.get(url.clone()))
+            .await
+            .unwrap();
+        assert_eq!(entry.body, "attempt 2");
+        assert_eq!(get_page(&config, &url).await.unwrap().body, "attempt 2");
+    }
+
+    #[tokio::test]
+    async fn stale_entries_are_revalidated() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/stale"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("ETag", "\"v1\"")
+                    .insert_header("Link", "<https://example.com/page?page=2>; rel=\"next\"")
+                    .set_body_string("grades"),
+            )
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/stale"))
+            .and(header("If-None-Match", "\"v1\""))
+            .respond_with(ResponseTemplate::new(304))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let config = test_config(&server.uri());
+        let url = page_url(&server, "/stale");
+
+        let first = get_page(&config, &url).await.unwrap();
+        let second = get_page(&config, &url).await.unwrap();
+        assert_eq!(second.body, "grades");
+        assert_eq!(second.etag.as_deref(), Some("\"v1\""));
+        assert_eq!(second.link, first.link);
+        assert!(second.link.unwrap().contains("page=2"));
+        assert!(second.fetched_at >= first.fetched_at);
+    }
+
+    #[tokio::test]
+    async fn offline_uses_only_the_cache() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/offline"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("grades"))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let mut config = test_config(&server.uri());
+        let url = page_url(&server, "/offline");
+        get_page(&config, &url).await.unwrap();
+
+        config.offline = true;
+        assert_eq!(get_page(&config, &url).await.unwrap().body, "grades");
+        let missing = Url::parse(&format!("{}/missing", server.uri())).unwrap();
+        let error = get_page(&config, &missing).await.unwrap_err();
+        assert!(error.to_string().starts_with("No cached copy of"));
+    }
+}
@@ -0,0 +1,310 @@
+use async_trait::async_trait;
+use color_eyre::{eyre::eyre, Result, Section};
+use futures::future::{join_all, try_join_all};
+
+use crate::{
+    canvas_api::{AssignmentGroup, CanvasAssignment, CanvasCourse, PlannerOverride},
+    config::{self, Exclusion},
+    discuss::is_complete,
+    fetch, fetch_paginated,
+    handle::{self, canvas_id, Target},
+    planner::{is_marked_done, load_overrides_or_warn},
+    progress::Progress,
+    source::{AssignmentSource, Course, Failure, Item, ItemKind, Loaded, Status, SubmissionKind},
+};
+
+pub struct CanvasSource;
+
+#[async_trait]
+impl AssignmentSource for CanvasSource {
+    fn name(&self) -> &'static str {
+        "Canvas"
+    }
+
+    async fn load(&self, progress: &Progress, config: &config::Config) -> Result<Loaded> {
+        let (courses, overrides) = progress
+            .wrap("Loading course list", async {
+                tokio::join!(
+                    load_courses(config),
+                    load_overrides_or_warn(progress, config)
+                )
+            })
+            .await;
+        let courses = courses?;
+        let overrides = &overrides;
+
+        let results = join_all(courses.into_iter().map(|course| async move {
+            progress
+                .wrap(
+                    &format!("Loading assignments for {}", course.name),
+                    load_assignments(config, course.id),
+                )
+                .await
+                .map(|assignments| {
+                    assignments
+                        .iter()
+                        .filter_map(|x| to_item(config, overrides, &course, x))
+                        .collect()
+                })
+                .map_err(|error| Failure {
+                    source: format!("Canvas course {}", course.name),
+                    error,
+                })
+        }))
+        .await;
+
+        Ok(Loaded::from_courses(results))
+    }
+}
+
+/// Loads the active courses that have not been excluded, sorted by name.
+pub async fn load_courses(config: &config::Config) -> Result<Vec<CanvasCourse>> {
+    let mut courses: Vec<CanvasCourse> = fetch_paginated(
+        config,
+        "/api/v1/courses?enrollment_state=active&include[]=total_scores&per_page=100",
+    )
+    .await?;
+
+    courses.retain(|x| {
+        !config.exclude.iter().any(|y| match y {
+            Exclusion::ByClassId { class_id } => class_id == &x.id,
+            _ => false,
+        })
+    });
+
+    courses.sort_by_key(|x| x.name.clone());
+
+    Ok(courses)
+}
+
+/// Finds a course by id, or by a case-insensitive match on its name or course code.
+pub fn find_course<'a>(courses: &'a [CanvasCourse], query: &str) -> Result<&'a CanvasCourse> {
+    if let Some(course) = query
+        .parse::<i64>()
+        .ok()
+        .and_then(|id| courses.iter().find(|x| x.id == id))
+    {
+        return Ok(course);
+    }
+
+    let needle = query.to_lowercase();
+    let matches: Vec<_> = courses
+        .iter()
+        .filter(|x| {
+            x.name.to_lowercase().contains(&needle)
+                || x.course_code
+                    .as_ref()
+                    .is_some_and(|x| x.to_lowercase().contains(&needle))
+        })
+        .collect();
+
+    match &matches[..] {
+        [course] => Ok(course),
+        [] => Err(eyre!("No active course matches `{}`", query)),
+        _ => Err(eyre!("`{}` matches more than one course", query)).suggestion(format!(
+            "Did you mean one of: {}",
+            matches
+                .iter()
+                .map(|x| format!("{} ({})", x.name, x.id))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+/// Finds an assignment by id, or by a case-insensitive match on its name.
+pub fn find_assignment<'a>(
+    assignments: impl IntoIterator<Item = &'a CanvasAssignment>,
+    query: &str,
+) -> Result<&'a CanvasAssignment> {
+    let assignments: Vec<_> = assignments.into_iter().collect();
+    if let Some(assignment) = query
+        .parse::<i64>()
+        .ok()
+        .and_then(|id| assignments.iter().find(|x| x.id == id))
+    {
+        return Ok(assignment);
+    }
+
+    let needle = query.to_lowercase();
+    let matches: Vec<_> = assignments
+        .into_iter()
+        .filter(|x| x.name.to_lowercase().contains(&needle))
+        .collect();
+
+    match &matches[..] {
+        [assignment] => Ok(assignment),
+        [] => Err(eyre!("No assignment matches `{}`", query)),
+        _ => Err(eyre!("`{}` matches more than one assignment", query)).suggestion(format!(
+            "Did you mean one of: {}",
+            matches
+                .iter()
+                .map(|x| format!("{} ({})", x.name.trim(), x.id))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+/// Finds the assignment an item argument refers to, such as a handle from the last listing.
+pub async fn lookup_assignment(
+    config: &config::Config,
+    query: &str,
+) -> Result<(CanvasCourse, CanvasAssignment)> {
+    match handle::resolve(config, query).await? {
+        Target::Key {
+            key, course_key, ..
+        } => {
+            let id = canvas_id(&key, "assignment")
+                .ok_or_else(|| eyre!("`{}` is not a Canvas assignment", query))?;
+            match course_key.and_then(|x| canvas_id(&x, "course")) {
+                Some(course_id) => {
+                    let course_url = format!("/api/v1/courses/{}", course_id);
+                    let assignment_url = assignment_url(course_id, id);
+                    tokio::try_join!(fetch(config, &course_url), fetch(config, &assignment_url))
+                }
+                None => search_assignments(config, &id.to_string()).await,
+            }
+        }
+        Target::Id(id) => search_assignments(config, &id.to_string()).await,
+        Target::Name(name) => search_assignments(config, &name).await,
+    }
+}
+
+/// Searches the assignments of every active course, for when only an id or a name is known.
+async fn search_assignments(
+    config: &config::Config,
+    query: &str,
+) -> Result<(CanvasCourse, CanvasAssignment)> {
+    let progress = Progress::new();
+    let courses = progress
+        .wrap("Loading course list", load_courses(config))
+        .await?;
+    let assignments = {
+        let progress = &progress;
+        try_join_all(courses.iter().map(|course| async move {
+            progress
+                .wrap(
+                    &format!("Loading assignments for {}", course.name),
+                    load_assignments(config, course.id),
+                )
+                .await
+        }))
+        .await
+    };
+    progress.finish();
+    let assignments = assignments?;
+
+    let assignment = find_assignment(assignments.iter().flatten(), query)?.clone();
+    let course = courses
+        .into_iter()
+        .find(|x| x.id == assignment.course_id)
+        .unwrap();
+
+    Ok((course, assignment))
+}
+
+pub async fn load_assignment_groups(
+    config: &config::Config,
+    course_id: i64,
+) -> Result<Vec<AssignmentGroup>> {
+    fetch_paginated(
+        config,
+        &format!(
+            "/api/v1/courses/{}/assignment_groups?include[]=assignments&include[]=submission&per_page=100",
+            course_id
+        ),
+    )
+    .await
+}
+
+pub fn assignments_url(course_id: i64) -> String {
+    format!(
+        "/api/v1/courses/{}/assignments?per_page=100&include=submission",
+        course_id
+    )
+}
+
+/// A single assignment, along with the user's submission.
+pub fn assignment_url(course_id: i64, id: i64) -> String {
+    format!(
+        "/api/v1/courses/{}/assignments/{}?include[]=submission",
+        course_id, id
+    )
+}
+
+pub async fn load_assignments(
+    config: &config::Config,
+    course_id: i64,
+) -> Result<Vec<CanvasAssignment>> {
+    fetch_paginated(config, &assignments_url(course_id)).await
+}
+
+/// Assignments without points or a submission are not something the user can turn in.
+fn to_item(
+    config: &config::Config,
+    overrides: &[PlannerOverride],
+    course: &CanvasCourse,
+    assignment: &CanvasAssignment,
+) -> Option<Item> {
+    assignment.points_possible?;
+    let submission = assignment.submission.as_ref()?;
+
+    // The planner tracks quizzes and discussions by their own ids rather than the assignment's.
+    let marked_done = is_marked_done(overrides, "assignment", assignment.id)
+        || assignment
+            .quiz_id
+            .is_some_and(|id| is_marked_done(overrides, "quiz", id))
+        || assignment
+            .discussion_topic
+            .as_ref()
+            .is_some_and(|x| is_marked_done(overrides, "discussion_topic", x.id));
+
+    let status = if marked_done {
+        Status::Done
+    } else if submission.submitted_at.is_none() {
+        Status::Pending
+    } else if assignment.discussion_topic.is_some()
+        && !is_complete(config, assignment, &submission.discussion_entries)
+    {
+        Status::InProgress
+    } else {
+        Status::Submitted
+    };
+
+    let kind = if assignment.is_quiz_assignment == Some(true)
+        || assignment
+            .submission_types
+            .iter()
+            .any(|x| x == "online_quiz")
+    {
+        ItemKind::Quiz
+    } else if assignment.discussion_topic.is_some() {
+        ItemKind::Discussion
+    } else {
+        ItemKind::Assignment
+    };
+
+    Some(Item {
+        key: format!("canvas:assignment:{}", assignment.id),
+        kind,
+        course: Course {
+            key: format!("canvas:course:{}", course.id),
+            name: course.name.clone(),
+        },
+        title: assignment.name.trim().to_string(),
+        due_at: assignment.due_at,
+        late_due_at: None,
+        status,
+        points: assignment.points_possible,
+        url: assignment.html_url.clone(),
+        submission_kinds: assignment
+            .submission_types
+            .iter()
+            .map(|x| SubmissionKind::from_canvas(x))
+            .collect(),
+        locked: assignment.locked_for_user,
+        other_urls: vec![],
+        other_keys: vec![],
+    })
+}
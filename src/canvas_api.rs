@@ -1,281 +1,432 @@
 use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{Map, Value};
+
+// Canvas instances differ in which fields they return, so only the fields we rely on are
+// required. Everything else is optional, and unrecognized fields are kept in `extra`.
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CanvasAssignment {
     pub id: i64,
+    pub name: String,
+    pub course_id: i64,
+    pub html_url: String,
+    #[serde(default)]
+    pub submission_types: Vec<String>,
     pub description: Option<String>,
     pub due_at: Option<DateTime<Local>>,
     pub unlock_at: Option<DateTime<Local>>,
     pub lock_at: Option<DateTime<Local>>,
     pub points_possible: Option<f64>,
-    pub grading_type: String,
-    pub assignment_group_id: i64,
-    pub grading_standard_id: Value,
-    pub created_at: String,
-    pub updated_at: String,
+    pub grading_type: Option<String>,
+    pub assignment_group_id: Option<i64>,
+    #[serde(default)]
     pub peer_reviews: bool,
+    #[serde(default)]
     pub automatic_peer_reviews: bool,
-    pub position: i64,
-    pub grade_group_students_individually: bool,
+    pub peer_review_count: Option<i64>,
+    #[serde(default)]
     pub anonymous_peer_reviews: bool,
-    pub group_category_id: Value,
-    pub post_to_sis: bool,
-    pub moderated_grading: bool,
+    #[serde(default)]
     pub omit_from_final_grade: bool,
-    pub intra_group_peer_reviews: bool,
-    pub anonymous_instructor_annotations: bool,
-    pub anonymous_grading: bool,
-    pub graders_anonymous_to_graders: bool,
-    pub grader_count: i64,
-    pub grader_comments_visible_to_graders: bool,
-    pub final_grader_id: Value,
-    pub grader_names_visible_to_final_grader: bool,
-    pub allowed_attempts: i64,
-    pub secure_params: String,
-    pub course_id: i64,
-    pub name: String,
-    pub submission_types: Vec<String>,
-    pub has_submitted_submissions: bool,
-    pub due_date_required: bool,
-    pub max_name_length: i64,
-    pub in_closed_grading_period: bool,
-    pub is_quiz_assignment: bool,
-    pub can_duplicate: bool,
-    pub original_course_id: Option<i64>,
-    pub original_assignment_id: Option<i64>,
-    pub original_assignment_name: Option<String>,
-    pub original_quiz_id: Value,
-    pub workflow_state: String,
-    pub muted: bool,
-    pub html_url: String,
-    pub published: bool,
-    pub only_visible_to_overrides: bool,
+    pub allowed_attempts: Option<i64>,
+    pub is_quiz_assignment: Option<bool>,
+    pub quiz_id: Option<i64>,
+    pub workflow_state: Option<String>,
+    pub published: Option<bool>,
     pub submission: Option<Submission>,
+    #[serde(default)]
     pub locked_for_user: bool,
-    pub submissions_download_url: String,
-    pub post_manually: bool,
-    pub anonymize_students: bool,
-    pub require_lockdown_browser: bool,
     pub external_tool_tag_attributes: Option<ExternalToolTagAttributes>,
-    pub url: Option<String>,
-    pub is_quiz_lti_assignment: Option<bool>,
-    #[serde(default)]
-    pub frozen_attributes: Vec<String>,
     pub discussion_topic: Option<DiscussionTopic>,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Map<String, Value>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Submission {
-    pub id: i64,
+    pub id: Option<i64>,
+    pub assignment_id: Option<i64>,
     pub body: Option<String>,
     pub url: Option<String>,
     pub grade: Option<String>,
     pub score: Option<f64>,
     pub submitted_at: Option<String>,
-    pub assignment_id: i64,
-    pub user_id: i64,
     pub submission_type: Option<String>,
-    pub workflow_state: String,
-    pub grade_matches_current_submission: bool,
-    pub graded_at: Option<String>,
-    pub grader_id: Option<i64>,
+    pub workflow_state: Option<String>,
     pub attempt: Option<i64>,
-    pub cached_due_date: Option<String>,
     pub excused: Option<bool>,
-    pub late_policy_status: Value,
     pub points_deducted: Option<f64>,
-    pub grading_period_id: Option<i64>,
-    pub extra_attempts: Value,
-    pub posted_at: Option<String>,
+    #[serde(default)]
     pub late: bool,
+    #[serde(default)]
     pub missing: bool,
-    pub seconds_late: i64,
+    pub seconds_late: Option<i64>,
     pub entered_grade: Option<String>,
     pub entered_score: Option<f64>,
-    pub preview_url: String,
+    pub preview_url: Option<String>,
     #[serde(default)]
     pub attachments: Vec<Attachment>,
-    pub external_tool_url: Option<String>,
-    pub media_comment: Option<MediaComment>,
     #[serde(default)]
     pub discussion_entries: Vec<DiscussionEntry>,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Map<String, Value>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Attachment {
     pub id: i64,
-    pub uuid: String,
-    pub folder_id: Option<i64>,
-    pub display_name: String,
-    pub filename: String,
-    pub upload_status: String,
+    pub display_name: Option<String>,
+    pub filename: Option<String>,
     #[serde(rename = "content-type")]
-    pub content_type: String,
-    pub url: String,
-    pub size: i64,
-    pub created_at: String,
-    pub updated_at: String,
-    pub unlock_at: Value,
-    pub locked: bool,
-    pub hidden: bool,
-    pub lock_at: Value,
-    pub hidden_for_user: bool,
-    pub thumbnail_url: Option<String>,
-    pub modified_at: String,
-    pub mime_class: String,
-    pub media_entry_id: Value,
-    pub locked_for_user: bool,
-    pub preview_url: Option<String>,
-}
-
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct MediaComment {
-    #[serde(rename = "content-type")]
-    pub content_type: String,
-    pub display_name: Value,
-    pub media_id: String,
-    pub media_type: String,
-    pub url: String,
+    pub content_type: Option<String>,
+    pub url: Option<String>,
+    pub size: Option<i64>,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Map<String, Value>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DiscussionEntry {
     pub id: i64,
-    pub user_id: i64,
-    pub parent_id: Value,
-    pub created_at: String,
-    pub updated_at: String,
-    pub rating_count: Value,
-    pub rating_sum: Value,
-    pub user_name: String,
-    pub message: String,
-    pub user: User,
-    pub read_state: String,
-    pub forced_read_state: bool,
-}
-
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct User {
-    pub id: i64,
-    pub display_name: String,
-    pub avatar_image_url: String,
-    pub html_url: String,
-    pub pronouns: Value,
+    pub user_id: Option<i64>,
+    pub parent_id: Option<i64>,
+    pub created_at: Option<DateTime<Local>>,
+    pub user_name: Option<String>,
+    pub message: Option<String>,
+    pub read_state: Option<String>,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Map<String, Value>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ExternalToolTagAttributes {
-    pub url: String,
+    pub url: Option<String>,
     pub new_tab: Option<bool>,
-    pub resource_link_id: String,
-    pub external_data: Option<String>,
-    pub content_type: String,
-    pub content_id: i64,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Map<String, Value>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DiscussionTopic {
     pub id: i64,
     pub title: String,
-    pub last_reply_at: Option<String>,
-    pub created_at: String,
-    pub delayed_post_at: Value,
-    pub posted_at: Option<String>,
-    pub assignment_id: i64,
-    pub root_topic_id: Value,
-    pub position: Value,
-    pub podcast_has_student_posts: bool,
-    pub discussion_type: String,
-    pub lock_at: Value,
-    pub allow_rating: bool,
-    pub only_graders_can_rate: bool,
-    pub sort_by_rating: bool,
-    pub is_section_specific: bool,
+    pub message: Option<String>,
+    pub html_url: Option<String>,
+    pub posted_at: Option<DateTime<Local>>,
+    /// Which course an announcement belongs to, as `course_<id>`.
+    pub context_code: Option<String>,
+    pub assignment_id: Option<i64>,
     pub user_name: Option<String>,
-    pub discussion_subentry_count: i64,
-    pub permissions: Permissions,
-    pub require_initial_post: Value,
-    pub user_can_see_posts: bool,
-    pub podcast_url: Value,
-    pub read_state: String,
-    pub unread_count: i64,
-    pub subscribed: bool,
-    pub attachments: Vec<Value>,
-    pub published: bool,
-    pub can_unpublish: bool,
-    pub locked: bool,
-    pub can_lock: bool,
-    pub comments_disabled: bool,
-    pub author: Author,
-    pub html_url: String,
-    pub url: String,
-    pub pinned: bool,
-    pub group_category_id: Value,
-    pub can_group: bool,
-    pub topic_children: Vec<Value>,
-    pub group_topic_children: Vec<Value>,
+    pub discussion_subentry_count: Option<i64>,
+    pub require_initial_post: Option<bool>,
+    pub read_state: Option<String>,
+    pub unread_count: Option<i64>,
+    #[serde(default)]
     pub locked_for_user: bool,
-    pub message: String,
-    pub todo_date: Value,
+    pub permissions: Option<Permissions>,
+    pub todo_date: Option<DateTime<Local>>,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Map<String, Value>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Permissions {
-    pub attach: bool,
-    pub update: bool,
+    #[serde(default)]
     pub reply: bool,
-    pub delete: bool,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Map<String, Value>,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct Author {}
-
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CanvasCourse {
     pub id: i64,
     pub name: String,
-    pub account_id: i64,
-    pub uuid: String,
-    pub start_at: Option<String>,
-    pub grading_standard_id: Value,
-    pub is_public: Option<bool>,
-    pub created_at: String,
-    pub course_code: String,
-    pub default_view: String,
-    pub root_account_id: i64,
-    pub enrollment_term_id: i64,
-    pub license: Option<String>,
-    pub grade_passback_setting: Value,
-    pub end_at: Value,
-    pub public_syllabus: bool,
-    pub public_syllabus_to_auth: bool,
-    pub storage_quota_mb: i64,
-    pub is_public_to_auth_users: bool,
+    pub course_code: Option<String>,
+    pub workflow_state: Option<String>,
+    pub enrollment_term_id: Option<i64>,
+    pub time_zone: Option<String>,
+    #[serde(default)]
     pub apply_assignment_group_weights: bool,
-    pub calendar: Calendar,
-    pub time_zone: String,
-    pub blueprint: bool,
-    pub enrollments: Vec<Enrollment>,
+    #[serde(default)]
     pub hide_final_grades: bool,
+    #[serde(default)]
+    pub enrollments: Vec<Enrollment>,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Enrollment {
+    #[serde(rename = "type")]
+    pub type_field: Option<String>,
+    pub role: Option<String>,
+    pub user_id: Option<i64>,
+    pub enrollment_state: Option<String>,
+    /// Only present when requested with `include[]=total_scores`.
+    pub computed_current_score: Option<f64>,
+    pub computed_final_score: Option<f64>,
+    pub computed_current_grade: Option<String>,
+    pub computed_final_grade: Option<String>,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlannerItem {
+    pub plannable_id: i64,
+    pub plannable_type: String,
+    pub plannable: Plannable,
+    pub plannable_date: Option<DateTime<Local>>,
+    pub course_id: Option<i64>,
+    pub context_name: Option<String>,
+    pub html_url: Option<String>,
+    /// `false` when the item cannot be submitted, otherwise an object of submission flags.
+    #[serde(default)]
+    pub submissions: Value,
+    pub planner_override: Option<PlannerOverride>,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Plannable {
+    pub id: Option<i64>,
+    pub title: String,
+    pub assignment_id: Option<i64>,
+    pub points_possible: Option<f64>,
+    pub due_at: Option<DateTime<Local>>,
+    pub todo_date: Option<DateTime<Local>>,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlannerOverride {
+    pub id: i64,
+    pub plannable_type: String,
+    pub plannable_id: i64,
+    #[serde(default)]
+    pub marked_complete: bool,
+    #[serde(default)]
+    pub dismissed: bool,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AssignmentGroup {
+    pub id: i64,
+    pub name: String,
+    pub position: Option<i64>,
+    pub group_weight: Option<f64>,
+    pub rules: Option<GroupRules>,
+    #[serde(default)]
+    pub assignments: Vec<CanvasAssignment>,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GroupRules {
+    pub drop_lowest: Option<usize>,
+    pub drop_highest: Option<usize>,
+    #[serde(default)]
+    pub never_drop: Vec<i64>,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Map<String, Value>,
+}
+
+/// Where to send a file, from the first step of a Canvas file upload.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UploadSlot {
+    pub upload_url: String,
+    #[serde(default)]
+    pub upload_params: Map<String, Value>,
+    pub file_param: Option<String>,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Conversation {
+    pub id: i64,
+    pub subject: Option<String>,
+    /// `read`, `unread` or `archived`.
     pub workflow_state: String,
-    pub restrict_enrollments_to_course_dates: bool,
-    pub overridden_course_visibility: Option<String>,
+    pub last_message: Option<String>,
+    pub last_message_at: Option<DateTime<Local>>,
+    pub message_count: Option<i64>,
+    pub context_name: Option<String>,
+    pub context_code: Option<String>,
+    #[serde(default)]
+    pub participants: Vec<Participant>,
+    /// Only included when a single conversation is requested, newest first.
+    #[serde(default)]
+    pub messages: Vec<ConversationMessage>,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Map<String, Value>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct Calendar {
-    pub ics: String,
+pub struct Participant {
+    pub id: i64,
+    pub name: String,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Map<String, Value>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct Enrollment {
-    #[serde(rename = "type")]
-    pub type_field: String,
-    pub role: String,
-    pub role_id: i64,
-    pub user_id: i64,
-    pub enrollment_state: String,
-    pub limit_privileges_to_course_section: bool,
+pub struct ConversationMessage {
+    pub id: i64,
+    pub created_at: Option<DateTime<Local>>,
+    pub body: String,
+    pub author_id: i64,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Map<String, Value>,
+}
+
+/// A user who can be messaged, from the recipient search.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Recipient {
+    pub id: Value,
+    pub name: String,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Map<String, Value>,
+}
+
+/// The full thread of a discussion, from `discussion_topics/:id/view`.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiscussionView {
+    #[serde(default)]
+    pub participants: Vec<ViewParticipant>,
+    #[serde(default)]
+    pub unread_entries: Vec<i64>,
+    /// Top-level entries, with their replies nested inside.
+    #[serde(default)]
+    pub view: Vec<ViewEntry>,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ViewParticipant {
+    pub id: i64,
+    pub display_name: String,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ViewEntry {
+    pub id: i64,
+    pub user_id: Option<i64>,
+    pub parent_id: Option<i64>,
+    pub message: Option<String>,
+    pub created_at: Option<DateTime<Local>>,
+    #[serde(default)]
+    pub deleted: bool,
+    #[serde(default)]
+    pub replies: Vec<ViewEntry>,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CanvasUser {
+    pub id: i64,
+    pub name: String,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Map<String, Value>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::test_config, decode_json};
+
+    const ASSIGNMENT: &str =
+        include_str!("../tests/fixtures/canvas/assignment_with_submission.json");
+    const SPARSE_ASSIGNMENT: &str =
+        include_str!("../tests/fixtures/canvas/assignment_missing_fields.json");
+    const COURSE: &str = include_str!("../tests/fixtures/canvas/course_with_enrollments.json");
+    const TOPIC: &str = include_str!("../tests/fixtures/canvas/discussion_topic.json");
+
+    fn config(strict: bool) -> crate::config::Config {
+        let mut config = test_config("https://canvas.example.edu");
+        config.strict = strict;
+        config
+    }
+
+    #[test]
+    fn decodes_assignment_with_submission() {
+        for strict in [false, true] {
+            let assignment: CanvasAssignment =
+                decode_json(&config(strict), ASSIGNMENT.as_bytes()).unwrap();
+            assert_eq!(assignment.id, 1843210);
+            assert_eq!(assignment.points_possible, Some(20.0));
+            assert!(assignment.extra.contains_key("lti_context_id"));
+
+            let submission = assignment.submission.unwrap();
+            assert_eq!(submission.score, Some(18.0));
+            assert_eq!(submission.attempt, Some(2));
+            assert_eq!(
+                submission.attachments[0].content_type.as_deref(),
+                Some("text/x-python")
+            );
+            assert!(submission.extra.contains_key("grader_id"));
+        }
+    }
+
+    #[test]
+    fn decodes_course_with_enrollments() {
+        for strict in [false, true] {
+            let course: CanvasCourse = decode_json(&config(strict), COURSE.as_bytes()).unwrap();
+            assert!(course.apply_assignment_group_weights);
+            let enrollment = &course.enrollments[0];
+            assert_eq!(enrollment.type_field.as_deref(), Some("student"));
+            assert_eq!(enrollment.computed_current_score, Some(91.37));
+            assert!(enrollment.extra.contains_key("role_id"));
+            assert!(course.extra.contains_key("calendar"));
+        }
+    }
+
+    #[test]
+    fn decodes_discussion_topic() {
+        for strict in [false, true] {
+            let topic: DiscussionTopic = decode_json(&config(strict), TOPIC.as_bytes()).unwrap();
+            assert_eq!(topic.require_initial_post, Some(true));
+            assert!(topic.permissions.unwrap().reply);
+            assert!(topic.extra.contains_key("discussion_type"));
+        }
+    }
+
+    #[test]
+    fn missing_fields_are_tolerated_unless_strict() {
+        let assignment: CanvasAssignment =
+            decode_json(&config(false), SPARSE_ASSIGNMENT.as_bytes()).unwrap();
+        assert!(!assignment.peer_reviews);
+        assert_eq!(assignment.description, None);
+        let submission = assignment.submission.unwrap();
+        assert_eq!(submission.submitted_at, None);
+        assert!(submission.attachments.is_empty());
+
+        let error = decode_json::<CanvasAssignment>(&config(true), SPARSE_ASSIGNMENT.as_bytes())
+            .unwrap_err()
+            .to_string();
+        assert!(error.starts_with("Response is missing fields: "));
+        for field in [".description", ".peer_reviews", ".submission.score"] {
+            assert!(error.contains(field), "{} not in {}", field, error);
+        }
+        assert!(!error.contains(".due_at"));
+    }
+
+    #[test]
+    fn wrong_types_are_reported_with_their_path() {
+        let json = ASSIGNMENT.replace("\"points_possible\": 20.0", "\"points_possible\": \"20\"");
+        let error = decode_json::<CanvasAssignment>(&config(false), json.as_bytes()).unwrap_err();
+        assert!(error.to_string().starts_with("points_possible"));
+    }
 }
@@ -1,14 +1,23 @@
-use color_eyre::eyre::Result;
+use color_eyre::{eyre::eyre, Result, Section};
 use dirs::home_dir;
+use reqwest::Url;
 use serde::{Deserialize, Serialize};
 use std::fs::read_to_string;
 
+use crate::{discuss::Participation, matching::Matching, source::Item};
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
     pub canvas_url: String,
     pub token: String,
     #[serde(default)]
     pub gradescope_cookie: Option<String>,
+    /// Base URL of the Gradescope instance, for regional ones such as gradescope.ca.
+    #[serde(default = "default_gradescope_url")]
+    pub gradescope_url: String,
+    /// Gradescope terms to load, such as "Fall 2024". Only the current term is loaded if unset.
+    #[serde(default)]
+    pub gradescope_terms: Option<Vec<String>>,
     #[serde(default)]
     pub hide_overdue_after_days: Option<i64>,
     #[serde(default)]
@@ -19,26 +28,145 @@ pub struct Config {
     pub include: Vec<Inclusion>,
     #[serde(default)]
     pub hide_locked: bool,
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    #[serde(default = "default_retry_budget_secs")]
+    pub retry_budget_secs: u64,
+    #[serde(default)]
+    pub cache_ttl_secs: u64,
+    #[serde(default = "default_sources")]
+    pub sources: Vec<String>,
+    #[serde(default)]
+    pub matching: Matching,
+    #[serde(default)]
+    pub discussions: Participation,
+    #[serde(skip)]
+    pub offline: bool,
+    #[serde(skip)]
+    pub strict: bool,
+}
+
+fn default_gradescope_url() -> String {
+    "https://www.gradescope.com/".into()
+}
+
+fn default_sources() -> Vec<String> {
+    vec!["canvas".into(), "planner".into(), "gradescope".into()]
+}
+
+fn default_max_retries() -> u32 {
+    5
+}
+
+fn default_retry_budget_secs() -> u64 {
+    60
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(untagged)]
+#[allow(clippy::enum_variant_names)]
 pub enum Exclusion {
     ByClassId { class_id: i64 },
     ByAssignmentId { assignment_id: i64 },
+    ByGradescopeCourseId { gradescope_course_id: i64 },
+    ByGradescopeAssignmentId { gradescope_assignment_id: i64 },
+}
+
+impl Exclusion {
+    pub fn matches(&self, item: &Item) -> bool {
+        match self {
+            Exclusion::ByClassId { class_id } => {
+                item.course.key == format!("canvas:course:{}", class_id)
+            }
+            Exclusion::ByAssignmentId { assignment_id } => {
+                item.has_key(&format!("canvas:assignment:{}", assignment_id))
+            }
+            Exclusion::ByGradescopeCourseId {
+                gradescope_course_id,
+            } => item.course.key == format!("gradescope:course:{}", gradescope_course_id),
+            Exclusion::ByGradescopeAssignmentId {
+                gradescope_assignment_id,
+            } => item.has_key(&format!(
+                "gradescope:assignment:{}",
+                gradescope_assignment_id
+            )),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(untagged)]
 pub enum Inclusion {
     ByAssignmentId { assignment_id: i64 },
+    ByGradescopeAssignmentId { gradescope_assignment_id: i64 },
+}
+
+impl Inclusion {
+    pub fn matches(&self, item: &Item) -> bool {
+        match self {
+            Inclusion::ByAssignmentId { assignment_id } => {
+                item.has_key(&format!("canvas:assignment:{}", assignment_id))
+            }
+            Inclusion::ByGradescopeAssignmentId {
+                gradescope_assignment_id,
+            } => item.has_key(&format!(
+                "gradescope:assignment:{}",
+                gradescope_assignment_id
+            )),
+        }
+    }
 }
 
 pub fn read_config() -> Result<Config> {
-    let config = read_to_string(config_path())?;
-    Ok(toml::from_str(&config)?)
+    parse_config(&read_to_string(config_path())?)
+}
+
+fn parse_config(s: &str) -> Result<Config> {
+    let config: Config = toml::from_str(s)?;
+    // Every request is made relative to these, so a bad one is caught here rather than later.
+    check_base_url("canvas_url", &config.canvas_url)?;
+    check_base_url("gradescope_url", &config.gradescope_url)?;
+    Ok(config)
+}
+
+fn check_base_url(name: &str, value: &str) -> Result<()> {
+    match Url::parse(value) {
+        Ok(url) if !url.cannot_be_a_base() && url.host().is_some() => Ok(()),
+        _ => Err(eyre!("`{}` is not a valid URL: `{}`", name, value))
+            .suggestion("Write the whole address, including https://"),
+    }
 }
 
 pub fn config_path() -> std::path::PathBuf {
     home_dir().unwrap().join(".canvas.toml")
 }
+
+/// A configuration that sends every request to `server`, without retrying failures.
+#[cfg(test)]
+pub fn test_config(server: &str) -> Config {
+    toml::from_str(&format!(
+        "canvas_url = \"{0}\"\ntoken = \"token\"\ngradescope_url = \"{0}\"\ngradescope_cookie = \"session\"\nmax_retries = 0\n",
+        server
+    ))
+    .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls_without_a_scheme_are_rejected() {
+        let error = parse_config(
+            "canvas_url = \"https://canvas.example.edu\"\ntoken = \"x\"\ngradescope_url = \"gradescope.ca\"",
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("`gradescope_url`"));
+
+        let config = parse_config(
+            "canvas_url = \"https://canvas.example.edu\"\ntoken = \"x\"\ngradescope_url = \"https://www.gradescope.ca/\"",
+        )
+        .unwrap();
+        assert_eq!(config.gradescope_url, "https://www.gradescope.ca/");
+    }
+}
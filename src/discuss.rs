@@ -0,0 +1,375 @@
+use std::collections::HashMap;
+
+use chrono::Local;
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result, Section,
+};
+use colored::Colorize;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    cache,
+    canvas::{assignment_url, assignments_url, lookup_assignment},
+    canvas_api::{
+        CanvasAssignment, CanvasCourse, CanvasUser, DiscussionEntry, DiscussionTopic,
+        DiscussionView, ViewEntry,
+    },
+    canvas_url, config,
+    editor::{compose, quote},
+    fetch, format_datetime, format_duration_full,
+    handle::{self, canvas_id, Target},
+    html, send_json,
+    submit::markdown_to_html,
+};
+
+/// How many posts and replies a discussion needs before it counts as finished.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Participation {
+    /// Top-level posts, 1 unless set.
+    #[serde(default)]
+    pub posts: Option<usize>,
+    /// Replies to other entries, 1 unless set for discussions with peer reviews and 0 otherwise.
+    #[serde(default)]
+    pub replies: Option<usize>,
+    /// Courses that ask for a different number of posts or replies.
+    #[serde(default)]
+    pub courses: Vec<CourseParticipation>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CourseParticipation {
+    pub course_id: i64,
+    #[serde(default)]
+    pub posts: Option<usize>,
+    #[serde(default)]
+    pub replies: Option<usize>,
+}
+
+impl Participation {
+    /// The posts and replies a discussion in the course needs.
+    pub fn required(&self, course_id: i64, peer_reviews: bool) -> (usize, usize) {
+        let course = self.courses.iter().find(|x| x.course_id == course_id);
+        let posts = course.and_then(|x| x.posts).or(self.posts).unwrap_or(1);
+        let replies = course
+            .and_then(|x| x.replies)
+            .or(self.replies)
+            .unwrap_or(if peer_reviews { 1 } else { 0 });
+        (posts, replies)
+    }
+}
+
+/// Whether the user's entries in a graded discussion meet the course's requirement.
+pub fn is_complete(
+    config: &config::Config,
+    assignment: &CanvasAssignment,
+    entries: &[DiscussionEntry],
+) -> bool {
+    let (posts, replies) = config
+        .discussions
+        .required(assignment.course_id, assignment.peer_reviews);
+    let posted = entries.iter().filter(|x| x.parent_id.is_none()).count();
+    posted >= posts && entries.len() - posted >= replies
+}
+
+fn topic_url(course_id: i64, topic_id: i64) -> String {
+    format!(
+        "/api/v1/courses/{}/discussion_topics/{}",
+        course_id, topic_id
+    )
+}
+
+fn view_url(course_id: i64, topic_id: i64) -> String {
+    format!("{}/view", topic_url(course_id, topic_id))
+}
+
+/// Finds the discussion an item argument refers to, along with its assignment if it is graded.
+async fn lookup_topic(
+    config: &config::Config,
+    query: &str,
+) -> Result<(CanvasCourse, DiscussionTopic, Option<CanvasAssignment>)> {
+    // Links to discussions, and ungraded discussions from the planner, are not assignments.
+    if let Target::Key {
+        key,
+        course_key: Some(course_key),
+        ..
+    } = handle::resolve(config, query).await?
+    {
+        if let (Some(id), Some(course_id)) = (
+            canvas_id(&key, "discussion_topic"),
+            canvas_id(&course_key, "course"),
+        ) {
+            let course_url = format!("/api/v1/courses/{}", course_id);
+            let topic_url = topic_url(course_id, id);
+            let (course, topic): (CanvasCourse, DiscussionTopic) =
+                tokio::try_join!(fetch(config, &course_url), fetch(config, &topic_url))?;
+            let assignment = match topic.assignment_id {
+                Some(assignment_id) => {
+                    Some(fetch(config, &assignment_url(course_id, assignment_id)).await?)
+                }
+                None => None,
+            };
+            return Ok((course, topic, assignment));
+        }
+    }
+
+    let (course, assignment) = lookup_assignment(config, query).await?;
+    let topic = assignment
+        .discussion_topic
+        .as_ref()
+        .ok_or_else(|| eyre!("{} is not a discussion", assignment.name.trim()))?;
+    let topic = fetch(config, &topic_url(course.id, topic.id)).await?;
+    Ok((course, topic, Some(assignment)))
+}
+
+async fn load_view(
+    config: &config::Config,
+    course: &CanvasCourse,
+    topic: &DiscussionTopic,
+    query: &str,
+) -> Result<DiscussionView> {
+    let view = fetch(config, &view_url(course.id, topic.id))
+        .await
+        .wrap_err_with(|| eyre!("Unable to load the replies to {}", topic.title.trim()));
+    if topic.require_initial_post == Some(true) {
+        view.suggestion(format!(
+            "Replies are hidden until you post, with `canvas discuss reply {}`",
+            query
+        ))
+    } else {
+        view
+    }
+}
+
+/// Every entry in the thread along with how deeply it is nested, in reading order.
+fn flatten<'a>(entries: &'a [ViewEntry], depth: usize, out: &mut Vec<(usize, &'a ViewEntry)>) {
+    for entry in entries {
+        out.push((depth, entry));
+        flatten(&entry.replies, depth + 1, out);
+    }
+}
+
+fn indent(text: &str, width: usize) -> String {
+    text.lines()
+        .map(|x| {
+            format!("{:width$}{}", "", x, width = width)
+                .trim_end()
+                .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub async fn run_discuss(config: &config::Config, query: &str) -> Result<()> {
+    let (course, topic, assignment) = lookup_topic(config, query).await?;
+    let (view, me) = tokio::join!(
+        load_view(config, &course, &topic, query),
+        fetch::<CanvasUser>(config, "/api/v1/users/self")
+    );
+    let me = me?;
+
+    println!("{}", topic.title.trim().bold().underline());
+    println!("{}", course.name);
+    let now = Local::now();
+    let mut byline = vec![];
+    if let Some(author) = &topic.user_name {
+        byline.push(author.clone());
+    }
+    if let Some(posted_at) = topic.posted_at {
+        byline.push(format!(
+            "posted {} ({})",
+            format_datetime(posted_at),
+            format_duration_full(now, posted_at)
+        ));
+    }
+    if !byline.is_empty() {
+        println!("{}", byline.join(", ").bright_black());
+    }
+    if let Some(message) = topic.message.as_deref().filter(|x| !x.trim().is_empty()) {
+        println!();
+        println!("{}", html::render(message));
+    }
+
+    let view = match view {
+        Ok(view) => view,
+        Err(_) if topic.require_initial_post == Some(true) => {
+            println!();
+            println!(
+                "{}",
+                format!(
+                    "Replies are hidden until you post, with `canvas discuss reply {}`.",
+                    query
+                )
+                .yellow()
+            );
+            return Ok(());
+        }
+        Err(error) => return Err(error),
+    };
+
+    let mut entries = vec![];
+    flatten(&view.view, 0, &mut entries);
+    let names: HashMap<_, _> = view
+        .participants
+        .iter()
+        .map(|x| (x.id, x.display_name.as_str()))
+        .collect();
+
+    let mine: Vec<_> = entries
+        .iter()
+        .filter(|(_, x)| !x.deleted && x.user_id == Some(me.id))
+        .collect();
+    let posted = mine.iter().filter(|(_, x)| x.parent_id.is_none()).count();
+    let replied = mine.len() - posted;
+    let (posts, replies) = config.discussions.required(
+        course.id,
+        assignment.as_ref().is_some_and(|x| x.peer_reviews),
+    );
+    let progress = format!(
+        "You have {} of {} posts and {} of {} replies",
+        posted, posts, replied, replies
+    );
+    println!();
+    if posted >= posts && replied >= replies {
+        println!("{}", progress.green());
+    } else {
+        println!("{}", progress.yellow());
+    }
+
+    for (depth, entry) in &entries {
+        let width = depth * 4;
+        println!();
+        let author = match entry.user_id {
+            Some(id) if id == me.id => "You",
+            Some(id) => names.get(&id).copied().unwrap_or("Unknown user"),
+            None => "Unknown user",
+        };
+        let mut heading = format!(
+            "{:width$}{} {}",
+            "",
+            format!("#{}", entry.id).bright_black(),
+            author.bold(),
+            width = width
+        );
+        if let Some(created_at) = entry.created_at {
+            heading += &format!(" {}", format_duration_full(now, created_at).bright_black());
+        }
+        if view.unread_entries.contains(&entry.id) {
+            heading += &" (new)".green().bold().to_string();
+        }
+        println!("{}", heading);
+        match entry.message.as_deref().filter(|_| !entry.deleted) {
+            Some(message) => println!("{}", indent(&html::render(message), width + 2)),
+            None => println!(
+                "{:width$}{}",
+                "",
+                "(deleted)".bright_black(),
+                width = width + 2
+            ),
+        }
+    }
+
+    if let Some(url) = &topic.html_url {
+        println!();
+        println!("{}", url);
+    }
+
+    Ok(())
+}
+
+/// The entry being replied to, or the topic itself, for the context part of the editor template.
+fn reply_context(topic: &DiscussionTopic, entry: Option<(&str, &ViewEntry)>) -> String {
+    let mut context = format!("#\n# {}\n", topic.title.trim());
+    let (heading, message) = match entry {
+        Some((author, entry)) => (format!("Replying to {}", author), entry.message.as_deref()),
+        None => (
+            "Posting to the discussion".to_string(),
+            topic.message.as_deref(),
+        ),
+    };
+    context += &format!("# {}\n", heading);
+    if let Some(message) = message.filter(|x| !x.trim().is_empty()) {
+        context += "#\n";
+        context += &quote(&html::render_plain(message));
+        context += "\n";
+    }
+    context
+}
+
+/// Posts a new entry to a discussion, or a reply to one of its entries, written in `$EDITOR`.
+pub async fn run_reply(config: &config::Config, query: &str, to: Option<i64>) -> Result<()> {
+    let (course, topic, assignment) = lookup_topic(config, query).await?;
+
+    let parent = match to {
+        Some(id) => {
+            let view = load_view(config, &course, &topic, query).await?;
+            let mut entries = vec![];
+            flatten(&view.view, 0, &mut entries);
+            let (_, entry) = entries
+                .into_iter()
+                .find(|(_, x)| x.id == id)
+                .ok_or_else(|| eyre!("{} has no entry #{}", topic.title.trim(), id))
+                .suggestion(format!(
+                    "Entry numbers are shown by `canvas discuss {}`",
+                    query
+                ))?;
+            let author = view
+                .participants
+                .iter()
+                .find(|x| Some(x.id) == entry.user_id)
+                .map(|x| x.display_name.clone())
+                .unwrap_or_else(|| "Unknown user".into());
+            Some((author, entry.clone()))
+        }
+        None => None,
+    };
+
+    let text = compose(
+        &format!("discussion-{}", topic.id),
+        "",
+        &reply_context(&topic, parent.as_ref().map(|(a, e)| (a.as_str(), e))),
+    )
+    .await?;
+
+    let url = match &parent {
+        Some((_, entry)) => format!(
+            "{}/entries/{}/replies",
+            topic_url(course.id, topic.id),
+            entry.id
+        ),
+        None => format!("{}/entries", topic_url(course.id, topic.id)),
+    };
+    let posted: DiscussionEntry = send_json(
+        config,
+        Method::POST,
+        &url,
+        &json!({ "message": markdown_to_html(&text) }),
+    )
+    .await
+    .wrap_err_with(|| eyre!("Unable to post to {}", topic.title.trim()))?;
+
+    cache::invalidate(&canvas_url(config, &view_url(course.id, topic.id))).await;
+    cache::invalidate(&canvas_url(config, &topic_url(course.id, topic.id))).await;
+    if let Some(assignment) = &assignment {
+        cache::invalidate(&canvas_url(config, &assignments_url(course.id))).await;
+        cache::invalidate(&canvas_url(
+            config,
+            &assignment_url(course.id, assignment.id),
+        ))
+        .await;
+    }
+
+    let summary = match &parent {
+        Some((author, _)) => format!("Replied to {} in {}", author, topic.title.trim()),
+        None => format!("Posted to {}", topic.title.trim()),
+    };
+    println!(
+        "{} {}",
+        summary.bold(),
+        format!("#{}", posted.id).bright_black()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,125 @@
+use std::{
+    env,
+    io::ErrorKind,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result, Section,
+};
+use tokio::{fs, io::AsyncWriteExt, process::Command};
+
+const SCISSORS: &str = "# ------------------------ >8 ------------------------";
+
+/// Opens `$VISUAL` or `$EDITOR` on `initial`, and returns what the user wrote.
+///
+/// `context` is shown below a scissors line, like in `git commit --verbose`, and everything from
+/// that line on is removed again afterwards.
+pub async fn compose(name: &str, initial: &str, context: &str) -> Result<String> {
+    let template = format!(
+        "{}\n\n{}\n# Do not modify or remove the line above.\n# Everything below it will be ignored.\n{}",
+        initial, SCISSORS, context
+    );
+    let (path, mut file) = create_template(name).await?;
+    let written = file.write_all(template.as_bytes()).await;
+    drop(file);
+    if let Err(error) = written {
+        let _ = fs::remove_file(&path).await;
+        return Err(error).wrap_err_with(|| eyre!("Unable to write {}", path.display()));
+    }
+
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".into());
+    // The editor may be given with arguments, like `code --wait`.
+    let mut parts = editor.split_whitespace();
+    let status = Command::new(parts.next().unwrap_or("vi"))
+        .args(parts)
+        .arg(&path)
+        .status()
+        .await
+        .wrap_err_with(|| eyre!("Unable to run `{}`", editor))
+        .suggestion("Set $EDITOR to the editor you want to use")?;
+
+    let text = fs::read_to_string(&path).await;
+    let _ = fs::remove_file(&path).await;
+    if !status.success() {
+        return Err(eyre!("`{}` exited with {}", editor, status));
+    }
+    let text = text?;
+
+    let text = match text.find(SCISSORS) {
+        Some(i) => &text[..i],
+        None => &text[..],
+    }
+    .trim();
+    if text.is_empty() {
+        return Err(eyre!("Aborting due to empty message"));
+    }
+
+    Ok(text.to_string())
+}
+
+/// Creates a new file for the template that only the user can read.
+///
+/// The temporary directory is usually shared, so an existing file or symlink with the same name
+/// is never opened; another name is tried instead.
+async fn create_template(name: &str) -> Result<(PathBuf, fs::File)> {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.subsec_nanos())
+        .unwrap_or(0);
+    let mut last_error = None;
+    for attempt in 0..16 {
+        let path = env::temp_dir().join(format!(
+            "canvas-{}-{}-{:08x}.md",
+            name,
+            std::process::id(),
+            nanos.wrapping_add(attempt)
+        ));
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        match options.open(&path).await {
+            Ok(file) => return Ok((path, file)),
+            Err(error) if error.kind() == ErrorKind::AlreadyExists => last_error = Some(error),
+            Err(error) => {
+                return Err(error).wrap_err_with(|| eyre!("Unable to create {}", path.display()))
+            }
+        }
+    }
+    Err(last_error.unwrap()).wrap_err("Unable to create a temporary file for the editor")
+}
+
+/// Quotes each line of `text` for the context part of a template.
+pub fn quote(text: &str) -> String {
+    text.lines()
+        .map(|x| format!("# {}", x).trim_end().to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn templates_are_new_private_files() {
+        let (first, _) = create_template("test").await.unwrap();
+        let (second, _) = create_template("test").await.unwrap();
+        assert_ne!(first, second);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&first).await.unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let _ = fs::remove_file(&first).await;
+        let _ = fs::remove_file(&second).await;
+    }
+}
@@ -0,0 +1,732 @@
+use std::collections::HashMap;
+
+use color_eyre::{eyre::eyre, Result};
+use colored::Colorize;
+
+use crate::{
+    canvas::{find_assignment, find_course, load_assignment_groups, load_courses},
+    canvas_api::{AssignmentGroup, CanvasAssignment, CanvasCourse, Enrollment},
+    config,
+    gradescope::{self, GradescopeAssignment, GradescopeCourse},
+    matching::same_course,
+    source::Course,
+};
+
+/// The enrollment that carries the user's scores, if Canvas returned one.
+fn student_enrollment(course: &CanvasCourse) -> Option<&Enrollment> {
+    course
+        .enrollments
+        .iter()
+        .find(|x| x.computed_current_score.is_some() || x.computed_final_score.is_some())
+}
+
+fn format_percent(score: Option<f64>, grade: Option<&String>) -> String {
+    match (score, grade) {
+        (Some(score), Some(grade)) => format!("{:.2}% ({})", score, grade),
+        (Some(score), None) => format!("{:.2}%", score),
+        (None, _) => "-".into(),
+    }
+}
+
+/// Gradescope courses with their assignments, or nothing if Gradescope is not in use.
+///
+/// Grades from Canvas are still worth showing when Gradescope fails, so errors are only warnings.
+async fn load_gradescope(
+    config: &config::Config,
+) -> Vec<(GradescopeCourse, Vec<GradescopeAssignment>)> {
+    if config.gradescope_cookie.is_none() || !config.sources.iter().any(|x| x == "gradescope") {
+        return vec![];
+    }
+    match gradescope::load_selected(config).await {
+        Ok(courses) => courses,
+        Err(error) => {
+            eprintln!(
+                "{}",
+                "Warning: Gradescope scores failed to load".yellow().bold()
+            );
+            eprintln!("  {:#}", error);
+            eprintln!();
+            vec![]
+        }
+    }
+}
+
+fn gradescope_total(assignments: &[GradescopeAssignment]) -> Option<(f64, f64)> {
+    let (score, possible) = assignments
+        .iter()
+        .filter_map(|x| x.score)
+        .fold((0.0, 0.0), |(a, b), (score, max)| (a + score, b + max));
+    Some((score, possible)).filter(|(_, possible)| *possible > 0.0)
+}
+
+fn gradescope_course(course: &GradescopeCourse) -> Course {
+    Course {
+        key: format!("gradescope:course:{}", course.id),
+        name: course.name.clone(),
+    }
+}
+
+pub async fn run_grades(config: &config::Config, course: Option<String>) -> Result<()> {
+    let (courses, gradescope) = tokio::join!(load_courses(config), load_gradescope(config));
+    let courses = courses?;
+
+    match course {
+        Some(query) => {
+            let course = find_course(&courses, &query)?;
+            let groups = load_assignment_groups(config, course.id).await?;
+            print_course_grades(course, &groups);
+
+            let canvas_course = Course {
+                key: format!("canvas:course:{}", course.id),
+                name: course.name.clone(),
+            };
+            for (course, assignments) in &gradescope {
+                if same_course(&canvas_course, &gradescope_course(course), &config.matching) {
+                    print_gradescope_grades(course, assignments);
+                }
+            }
+        }
+        None => {
+            let width = courses.iter().map(|x| x.name.len()).max().unwrap_or(0);
+            for course in &courses {
+                let scores = if course.hide_final_grades {
+                    "hidden".bright_black().to_string()
+                } else if let Some(enrollment) = student_enrollment(course) {
+                    format!(
+                        "{}  {}",
+                        format_percent(
+                            enrollment.computed_current_score,
+                            enrollment.computed_current_grade.as_ref()
+                        )
+                        .bold(),
+                        format!(
+                            "final {}",
+                            format_percent(
+                                enrollment.computed_final_score,
+                                enrollment.computed_final_grade.as_ref()
+                            )
+                        )
+                        .bright_black()
+                    )
+                } else {
+                    "-".into()
+                };
+                println!("{:width$}  {}", course.name, scores, width = width);
+            }
+
+            if !gradescope.is_empty() {
+                println!();
+                println!("{}", "Gradescope".bold());
+                let width = gradescope
+                    .iter()
+                    .map(|(x, _)| x.name.trim().len())
+                    .max()
+                    .unwrap_or(0);
+                for (course, assignments) in &gradescope {
+                    let total = match gradescope_total(assignments) {
+                        Some((score, possible)) => format!(
+                            "{:.2}% {}",
+                            score / possible * 100.0,
+                            format!("({} / {})", score, possible).bright_black()
+                        ),
+                        None => "-".into(),
+                    };
+                    println!("{:width$}  {}", course.name.trim(), total, width = width);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn print_gradescope_grades(course: &GradescopeCourse, assignments: &[GradescopeAssignment]) {
+    println!("{} {}", "Gradescope:".bold(), course.name.trim().bold());
+    let width = assignments
+        .iter()
+        .map(|x| x.name.trim().len())
+        .max()
+        .unwrap_or(0);
+    for assignment in assignments {
+        let text = match assignment.score {
+            Some((score, max)) => format!("{} / {}", score, max),
+            None if assignment.submitted => "submitted".bright_black().to_string(),
+            None => "-".bright_black().to_string(),
+        };
+        println!(
+            "  {:width$}  {}",
+            assignment.name.trim(),
+            text,
+            width = width
+        );
+    }
+    if let Some((score, possible)) = gradescope_total(assignments) {
+        println!(
+            "  {:width$}  {}",
+            "Total",
+            format!(
+                "{} / {} ({:.2}%)",
+                score,
+                possible,
+                score / possible * 100.0
+            )
+            .bold(),
+            width = width
+        );
+    }
+    println!();
+}
+
+fn print_course_grades(course: &CanvasCourse, groups: &[AssignmentGroup]) {
+    println!("{}", course.name.bold().underline());
+    if !course.hide_final_grades {
+        if let Some(enrollment) = student_enrollment(course) {
+            println!(
+                "Current {}, final {}",
+                format_percent(
+                    enrollment.computed_current_score,
+                    enrollment.computed_current_grade.as_ref()
+                )
+                .bold(),
+                format_percent(
+                    enrollment.computed_final_score,
+                    enrollment.computed_final_grade.as_ref()
+                )
+            );
+        }
+    }
+    println!();
+
+    let mut groups: Vec<_> = groups.iter().collect();
+    groups.sort_by_key(|x| x.position);
+
+    for group in groups {
+        match group.group_weight {
+            Some(weight) if course.apply_assignment_group_weights => {
+                println!("{} ({}%)", group.name.bold(), weight)
+            }
+            _ => println!("{}", group.name.bold()),
+        }
+
+        let width = group
+            .assignments
+            .iter()
+            .map(|x| x.name.trim().len())
+            .max()
+            .unwrap_or(0);
+        let mut earned = 0.0;
+        let mut possible = 0.0;
+
+        for assignment in &group.assignments {
+            let (text, counted) = format_assignment_score(assignment);
+            println!(
+                "  {:width$}  {}",
+                assignment.name.trim(),
+                text,
+                width = width
+            );
+            if let Some((score, points)) = counted {
+                earned += score;
+                possible += points;
+            }
+        }
+
+        if !course.hide_final_grades && possible > 0.0 {
+            println!(
+                "  {:width$}  {}",
+                "Total",
+                format!(
+                    "{} / {} ({:.2}%)",
+                    earned,
+                    possible,
+                    earned / possible * 100.0
+                )
+                .bold(),
+                width = width
+            );
+        }
+        println!();
+    }
+}
+
+/// Describes the score on an assignment, and returns the points that count toward the total.
+fn format_assignment_score(assignment: &CanvasAssignment) -> (String, Option<(f64, f64)>) {
+    let points = assignment.points_possible.unwrap_or(0.0);
+    let submission = assignment.submission.as_ref();
+
+    if submission.and_then(|x| x.excused) == Some(true) {
+        return ("excused".bright_black().to_string(), None);
+    }
+
+    match submission.and_then(|x| x.score) {
+        Some(score) => {
+            let mut text = format!("{} / {}", score, points);
+            if let Some(deducted) = submission.and_then(|x| x.points_deducted) {
+                if deducted > 0.0 {
+                    text += &format!(" (-{} late)", deducted).red().to_string();
+                }
+            }
+            if assignment.omit_from_final_grade {
+                text += &" (not counted)".bright_black().to_string();
+                (text, None)
+            } else {
+                (text, Some((score, points)))
+            }
+        }
+        None => (format!("- / {}", points).bright_black().to_string(), None),
+    }
+}
+
+/// Tolerance used when searching for the grade a drop rule settles on.
+const EPSILON: f64 = 1e-8;
+
+/// A score that counts toward a group total.
+#[derive(Debug, Clone, Copy)]
+struct Scored {
+    assignment_id: i64,
+    score: f64,
+    points: f64,
+}
+
+/// Computes a course grade the way Canvas does, as a percentage.
+///
+/// `hypothetical` replaces or fills in scores by assignment id. Ungraded assignments are left out
+/// of the current grade and count as zero in the final grade. Returns `None` when nothing counts.
+pub fn course_grade(
+    groups: &[AssignmentGroup],
+    weighted: bool,
+    hypothetical: &HashMap<i64, f64>,
+    final_grade: bool,
+) -> Option<f64> {
+    let totals: Vec<_> = groups
+        .iter()
+        .map(|group| {
+            let (score, points) = group_total(group, hypothetical, final_grade);
+            (group.group_weight.unwrap_or(0.0), score, points)
+        })
+        .collect();
+
+    if weighted {
+        let relevant: Vec<_> = totals
+            .iter()
+            .filter(|(_, _, points)| *points > 0.0)
+            .collect();
+        let full_weight: f64 = relevant.iter().map(|(weight, _, _)| weight).sum();
+        if full_weight == 0.0 {
+            return None;
+        }
+        let grade: f64 = relevant
+            .iter()
+            .map(|(weight, score, points)| score / points * weight)
+            .sum();
+        // Canvas scales up when some groups have nothing graded, but not past 100% of weight.
+        if full_weight < 100.0 {
+            Some(grade * 100.0 / full_weight)
+        } else {
+            Some(grade)
+        }
+    } else {
+        let score: f64 = totals.iter().map(|(_, score, _)| score).sum();
+        let points: f64 = totals.iter().map(|(_, _, points)| points).sum();
+        if points == 0.0 {
+            None
+        } else {
+            Some(score / points * 100.0)
+        }
+    }
+}
+
+/// Points earned and possible in a group, after drop rules.
+fn group_total(
+    group: &AssignmentGroup,
+    hypothetical: &HashMap<i64, f64>,
+    final_grade: bool,
+) -> (f64, f64) {
+    let scored: Vec<_> = group
+        .assignments
+        .iter()
+        .filter_map(|assignment| scored(assignment, hypothetical, final_grade))
+        .collect();
+
+    let rules = group.rules.clone().unwrap_or_default();
+    let (fixed, mut candidates): (Vec<_>, Vec<_>) = scored
+        .into_iter()
+        .partition(|x| rules.never_drop.contains(&x.assignment_id));
+
+    if let Some(drop_lowest) = rules.drop_lowest.filter(|x| *x > 0) {
+        let keep = candidates.len().saturating_sub(drop_lowest);
+        candidates = keep_scores(candidates, &fixed, keep, true);
+    }
+    if let Some(drop_highest) = rules.drop_highest.filter(|x| *x > 0) {
+        let keep = candidates.len().saturating_sub(drop_highest);
+        candidates = keep_scores(candidates, &fixed, keep, false);
+    }
+
+    candidates
+        .iter()
+        .chain(&fixed)
+        .fold((0.0, 0.0), |(score, points), x| {
+            (score + x.score, points + x.points)
+        })
+}
+
+fn scored(
+    assignment: &CanvasAssignment,
+    hypothetical: &HashMap<i64, f64>,
+    final_grade: bool,
+) -> Option<Scored> {
+    if assignment.omit_from_final_grade || assignment.published == Some(false) {
+        return None;
+    }
+    let submission = assignment.submission.as_ref();
+    if submission.and_then(|x| x.excused) == Some(true) {
+        return None;
+    }
+
+    let score = match hypothetical.get(&assignment.id) {
+        Some(score) => Some(*score),
+        None if submission.and_then(|x| x.workflow_state.as_deref()) == Some("pending_review") => {
+            None
+        }
+        None => submission.and_then(|x| x.score),
+    };
+    let score = match score {
+        Some(score) => score,
+        None if final_grade => 0.0,
+        None => return None,
+    };
+
+    Some(Scored {
+        assignment_id: assignment.id,
+        score,
+        points: assignment.points_possible.unwrap_or(0.0),
+    })
+}
+
+/// Keeps `keep` of the candidates so that, together with `fixed`, the group percentage is as
+/// high as possible (or as low as possible when dropping highest scores).
+///
+/// Dropping by lowest percentage is not enough when point values differ, so like Canvas this
+/// searches for the resulting ratio `q`: the best set is the one with the largest
+/// `score - q * points`, and `q` is right when that set's sum is zero.
+fn keep_scores(
+    candidates: Vec<Scored>,
+    fixed: &[Scored],
+    keep: usize,
+    highest: bool,
+) -> Vec<Scored> {
+    let keep = keep.max(1);
+    if candidates.len() <= keep {
+        return candidates;
+    }
+
+    if candidates.iter().chain(fixed).all(|x| x.points == 0.0) {
+        let mut candidates = candidates;
+        candidates.sort_by(|a, b| a.score.total_cmp(&b.score));
+        if highest {
+            candidates.reverse();
+        }
+        candidates.truncate(keep);
+        return candidates;
+    }
+
+    let kept_at = |q: f64| {
+        let mut rated = candidates.clone();
+        rated.sort_by(|a, b| (a.score - q * a.points).total_cmp(&(b.score - q * b.points)));
+        if highest {
+            rated.reverse();
+        }
+        rated.truncate(keep);
+        let sum: f64 = rated
+            .iter()
+            .chain(fixed)
+            .map(|x| x.score - q * x.points)
+            .sum();
+        (sum, rated)
+    };
+
+    let ratios = candidates
+        .iter()
+        .chain(fixed)
+        .filter(|x| x.points > 0.0)
+        .map(|x| x.score / x.points);
+    let mut low = ratios.clone().fold(f64::INFINITY, f64::min);
+    let mut high = ratios.fold(f64::NEG_INFINITY, f64::max);
+    // Extra credit can push the ratio past every individual one.
+    let extra: f64 = candidates
+        .iter()
+        .chain(fixed)
+        .filter(|x| x.points == 0.0)
+        .map(|x| x.score)
+        .sum();
+    high += extra;
+
+    let (mut sum, mut kept) = kept_at((low + high) / 2.0);
+    while sum.abs() > EPSILON && high - low > EPSILON {
+        let mid = (low + high) / 2.0;
+        if sum < 0.0 {
+            high = mid;
+        } else {
+            low = mid;
+        }
+        let next = kept_at((low + high) / 2.0);
+        sum = next.0;
+        kept = next.1;
+    }
+
+    kept
+}
+
+fn find_group_assignment<'a>(
+    groups: &'a [AssignmentGroup],
+    query: &str,
+) -> Result<&'a CanvasAssignment> {
+    find_assignment(groups.iter().flat_map(|x| &x.assignments), query)
+}
+
+/// Parses a hypothetical score given as `<assignment>=<points>`.
+pub fn parse_hypothetical(s: &str) -> Result<(String, f64), String> {
+    let (assignment, score) = s
+        .rsplit_once('=')
+        .ok_or_else(|| format!("expected <assignment>=<points>, got `{}`", s))?;
+    let score = score
+        .trim()
+        .parse()
+        .map_err(|_| format!("`{}` is not a number", score))?;
+    Ok((assignment.trim().to_string(), score))
+}
+
+fn format_grade(grade: Option<f64>) -> String {
+    match grade {
+        Some(grade) => format!("{:.2}%", grade),
+        None => "-".into(),
+    }
+}
+
+pub async fn run_whatif(
+    config: &config::Config,
+    course: &str,
+    scores: &[(String, f64)],
+    target: Option<f64>,
+    solve: Option<String>,
+) -> Result<()> {
+    let courses = load_courses(config).await?;
+    let course = find_course(&courses, course)?;
+    let groups = load_assignment_groups(config, course.id).await?;
+    let weighted = course.apply_assignment_group_weights;
+
+    let mut hypothetical = HashMap::new();
+    for (query, score) in scores {
+        let assignment = find_group_assignment(&groups, query)?;
+        hypothetical.insert(assignment.id, *score);
+    }
+
+    println!("{}", course.name.bold().underline());
+    println!(
+        "Current grade: {}",
+        format_grade(course_grade(&groups, weighted, &HashMap::new(), false)).bold()
+    );
+    println!(
+        "Final grade (ungraded as zero): {}",
+        format_grade(course_grade(&groups, weighted, &HashMap::new(), true))
+    );
+
+    if !hypothetical.is_empty() {
+        println!(
+            "With what-if scores: {}",
+            format_grade(course_grade(&groups, weighted, &hypothetical, false)).bold()
+        );
+    }
+
+    match (target, solve) {
+        (Some(target), Some(query)) => {
+            let assignment = find_group_assignment(&groups, &query)?;
+            let points = assignment.points_possible.unwrap_or(0.0);
+            let grade_with = |score: f64| {
+                let mut hypothetical = hypothetical.clone();
+                hypothetical.insert(assignment.id, score);
+                course_grade(&groups, weighted, &hypothetical, false).unwrap_or(0.0)
+            };
+
+            println!();
+            if grade_with(0.0) >= target {
+                println!(
+                    "You reach {}% even with 0 on {}.",
+                    target,
+                    assignment.name.trim()
+                );
+            } else if grade_with(points) < target {
+                println!(
+                    "{}% is out of reach: full marks on {} gives {}.",
+                    target,
+                    assignment.name.trim(),
+                    format_grade(Some(grade_with(points)))
+                );
+            } else {
+                let (mut low, mut high) = (0.0, points);
+                while high - low > 0.001 {
+                    let mid = (low + high) / 2.0;
+                    if grade_with(mid) >= target {
+                        high = mid;
+                    } else {
+                        low = mid;
+                    }
+                }
+                println!(
+                    "You need {:.2} / {} ({:.2}%) on {} to reach {}%.",
+                    high,
+                    points,
+                    high / points * 100.0,
+                    assignment.name.trim(),
+                    target
+                );
+            }
+        }
+        (None, None) => {}
+        _ => return Err(eyre!("--target and --solve must be used together")),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas_api::{GroupRules, Submission};
+
+    fn graded(id: i64, score: Option<f64>, points: f64) -> CanvasAssignment {
+        CanvasAssignment {
+            id,
+            points_possible: Some(points),
+            published: Some(true),
+            submission: Some(Submission {
+                score,
+                workflow_state: Some(
+                    if score.is_some() {
+                        "graded"
+                    } else {
+                        "unsubmitted"
+                    }
+                    .into(),
+                ),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn group(
+        weight: f64,
+        rules: GroupRules,
+        assignments: Vec<CanvasAssignment>,
+    ) -> AssignmentGroup {
+        AssignmentGroup {
+            group_weight: Some(weight),
+            rules: Some(rules),
+            assignments,
+            ..Default::default()
+        }
+    }
+
+    fn grade(groups: &[AssignmentGroup], weighted: bool, final_grade: bool) -> f64 {
+        course_grade(groups, weighted, &HashMap::new(), final_grade).unwrap()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    // 0/1, 50/100 and 100/100 with the lowest dropped. Dropping the lowest percentage (0/1) would
+    // give 75%, but Canvas drops 50/100 instead and shows 99.01%.
+    fn unequal_points() -> Vec<CanvasAssignment> {
+        vec![
+            graded(1, Some(0.0), 1.0),
+            graded(2, Some(50.0), 100.0),
+            graded(3, Some(100.0), 100.0),
+        ]
+    }
+
+    #[test]
+    fn drop_lowest_with_unequal_points() {
+        let rules = GroupRules {
+            drop_lowest: Some(1),
+            ..Default::default()
+        };
+        let groups = [group(100.0, rules, unequal_points())];
+        assert_close(grade(&groups, false, false), 100.0 / 101.0 * 100.0);
+    }
+
+    #[test]
+    fn drop_highest_with_unequal_points() {
+        let rules = GroupRules {
+            drop_highest: Some(1),
+            ..Default::default()
+        };
+        let groups = [group(100.0, rules, unequal_points())];
+        // Dropping 100/100 leaves 50/101, which is lower than dropping 0/1 for 150/200.
+        assert_close(grade(&groups, false, false), 50.0 / 101.0 * 100.0);
+    }
+
+    #[test]
+    fn never_drop_is_kept() {
+        let rules = GroupRules {
+            drop_lowest: Some(1),
+            never_drop: vec![2],
+            ..Default::default()
+        };
+        let groups = [group(100.0, rules, unequal_points())];
+        // 50/100 has to stay, so 0/1 is dropped: 150/200.
+        assert_close(grade(&groups, false, false), 75.0);
+    }
+
+    #[test]
+    fn weighted_groups_with_nothing_graded() {
+        let homework = group(
+            40.0,
+            GroupRules::default(),
+            vec![graded(1, Some(8.0), 10.0), graded(2, Some(9.0), 10.0)],
+        );
+        let exams = group(60.0, GroupRules::default(), vec![graded(3, None, 100.0)]);
+        let groups = [homework, exams];
+
+        // The current grade scales the homework's 40% up to the whole grade, like Canvas's 85%.
+        assert_close(grade(&groups, true, false), 85.0);
+        // The final grade counts the missing exam as zero: 0.4 * 85%.
+        assert_close(grade(&groups, true, true), 34.0);
+        // Without weights, the points simply add up.
+        assert_close(grade(&groups, false, true), 17.0 / 120.0 * 100.0);
+    }
+
+    #[test]
+    fn excused_and_omitted_assignments_do_not_count() {
+        let mut excused = graded(2, None, 10.0);
+        excused.submission.as_mut().unwrap().excused = Some(true);
+        let mut omitted = graded(3, Some(0.0), 50.0);
+        omitted.omit_from_final_grade = true;
+        let groups = [group(
+            100.0,
+            GroupRules::default(),
+            vec![graded(1, Some(8.0), 10.0), excused, omitted],
+        )];
+
+        assert_close(grade(&groups, false, false), 80.0);
+        assert_close(grade(&groups, false, true), 80.0);
+    }
+
+    #[test]
+    fn hypothetical_scores_fill_in_ungraded_assignments() {
+        let groups = [group(
+            100.0,
+            GroupRules::default(),
+            vec![graded(1, Some(8.0), 10.0), graded(2, None, 10.0)],
+        )];
+        let hypothetical = HashMap::from([(2, 10.0)]);
+        let grade = course_grade(&groups, false, &hypothetical, false).unwrap();
+        assert_close(grade, 90.0);
+    }
+}
@@ -1,14 +1,37 @@
-use std::str::FromStr;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+#[cfg(unix)]
+use std::{fs::Permissions, os::unix::fs::PermissionsExt};
 
+use async_trait::async_trait;
 use chrono::{DateTime, Local};
 use color_eyre::{
     eyre::{eyre, Context},
-    Help, Result,
+    Help, Report, Result,
+};
+use colored::Colorize;
+use futures::future::{join_all, try_join_all};
+use lazy_static::lazy_static;
+use reqwest::{
+    header::{CONTENT_DISPOSITION, LAST_MODIFIED, LOCATION, SET_COOKIE},
+    redirect, Url,
 };
-use reqwest::Url;
-use scraper::{Html, Selector};
+use scraper::{CaseSensitivity, ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{fs, io::AsyncWriteExt};
 
-use crate::{config, CLIENT};
+use crate::{
+    cache::{self, stable_hash},
+    config::{self, Exclusion},
+    handle::{self, gradescope_id, Target},
+    progress::Progress,
+    source::{AssignmentSource, Course, Failure, Item, ItemKind, Loaded, Status, SubmissionKind},
+    CLIENT,
+};
 
 #[derive(Debug, Clone)]
 pub struct GradescopeCourse {
@@ -16,64 +39,331 @@ pub struct GradescopeCourse {
     pub name: String,
     pub assignment_count: usize,
     pub id: i64,
+    /// The term heading the course is listed under, such as "Fall 2024".
+    pub term: Option<String>,
+    /// Whether the course is in the most recent term of its list on the dashboard.
+    pub current: bool,
 }
 
 #[derive(Debug, Clone)]
 pub struct GradescopeAssignment {
+    /// Gradescope's id, or a negative one derived from the name when the page does not link it.
+    pub id: i64,
     pub name: String,
     pub submitted: bool,
+    pub release_at: Option<DateTime<Local>>,
     pub due_at: Option<DateTime<Local>>,
+    /// When late submissions stop being accepted, if the assignment allows them.
+    pub late_due_at: Option<DateTime<Local>>,
+    /// Points earned and possible, once the assignment is graded.
+    pub score: Option<(f64, f64)>,
     pub link: Option<String>,
 }
 
-async fn fetch(config: &config::Config, path: &str) -> Result<String> {
+/// Autograder output embedded in a submission page.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AutograderResults {
+    pub score: Option<f64>,
+    pub output: Option<String>,
+    #[serde(default)]
+    pub tests: Vec<TestCase>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TestCase {
+    pub name: Option<String>,
+    pub number: Option<String>,
+    pub score: Option<f64>,
+    pub max_score: Option<f64>,
+    pub status: Option<String>,
+    pub output: Option<String>,
+}
+
+lazy_static! {
+    // Logging in answers with redirects that carry the session cookies, so they are read by hand.
+    static ref LOGIN_CLIENT: reqwest::Client = reqwest::Client::builder()
+        .redirect(redirect::Policy::none())
+        .build()
+        .unwrap();
+}
+
+/// Where `gradescope login` keeps the session cookies, as a JSON object of names to values.
+fn session_path() -> PathBuf {
+    dirs::config_dir()
+        .unwrap()
+        .join("canvas")
+        .join("gradescope-session.json")
+}
+
+/// The `Cookie` header for the session saved by `gradescope login`, if there is one.
+pub fn load_session() -> Option<String> {
+    let data = std::fs::read(session_path()).ok()?;
+    let cookies: BTreeMap<String, String> = serde_json::from_slice(&data).ok()?;
+    Some(cookie_header(&cookies)).filter(|x| !x.is_empty())
+}
+
+fn cookie_header(cookies: &BTreeMap<String, String>) -> String {
+    cookies
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Only the user should be able to read the session, since it is as good as the password.
+async fn save_session(cookies: &BTreeMap<String, String>) -> Result<()> {
+    let path = session_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options
+        .open(&path)
+        .await
+        .wrap_err_with(|| eyre!("Unable to write {}", path.display()))?;
+    #[cfg(unix)]
+    file.set_permissions(Permissions::from_mode(0o600)).await?;
+    file.write_all(&serde_json::to_vec(cookies)?).await?;
+    Ok(())
+}
+
+/// Remembers the cookies a response sets, and forgets the ones it expires.
+fn update_cookies(cookies: &mut BTreeMap<String, String>, response: &reqwest::Response) {
+    for header in response.headers().get_all(SET_COOKIE) {
+        let Ok(header) = header.to_str() else {
+            continue;
+        };
+        let mut parts = header.split(';');
+        let Some((name, value)) = parts.next().and_then(|x| x.split_once('=')) else {
+            continue;
+        };
+        let expired = parts.any(|x| x.trim().eq_ignore_ascii_case("max-age=0"));
+        if expired || value.is_empty() {
+            cookies.remove(name.trim());
+        } else {
+            cookies.insert(name.trim().to_string(), value.trim().to_string());
+        }
+    }
+}
+
+fn session_expired() -> Report {
+    eyre!("Not logged in to Gradescope, or the session has expired")
+        .suggestion("Run `canvas gradescope login`")
+}
+
+/// Fails unless there are Gradescope credentials to use.
+fn require_login(config: &config::Config) -> Result<()> {
+    if config.gradescope_cookie.is_none() {
+        return Err(eyre!("Not logged in to Gradescope"))
+            .suggestion("Run `canvas gradescope login`, or set `gradescope_cookie`");
+    }
+    Ok(())
+}
+
+/// Whether Gradescope sent the login form instead of the page that was asked for.
+fn is_login_page(body: &str) -> bool {
+    body.contains("name=\"session[email]\"")
+}
+
+/// `gradescope_url` is checked when the config is read, so it can always be joined.
+fn gradescope_url(config: &config::Config, path: &str) -> Url {
+    Url::from_str(&config.gradescope_url)
+        .unwrap()
+        .join(path)
+        .unwrap()
+}
+
+fn request(config: &config::Config, url: &Url) -> reqwest::RequestBuilder {
     CLIENT
-        .get(
-            Url::from_str("https://www.gradescope.com/")
-                .unwrap()
-                .join(path)
-                .unwrap(),
-        )
+        .get(url.clone())
         .header("Cookie", config.gradescope_cookie.as_ref().unwrap())
-        .send()
+}
+
+async fn fetch(config: &config::Config, path: &str) -> Result<String> {
+    let url = gradescope_url(config, path);
+    let body = cache::get(config, &url, || request(config, &url))
         .await
-        .wrap_err_with(|| eyre!("Unable to fetch {}", path))?
-        .error_for_status()
-        .wrap_err("Server returned error")
+        .wrap_err_with(|| eyre!("Unable to fetch {}", path))
         .suggestion("Make sure your credentials are valid")?
-        .text()
-        .await
-        .wrap_err("Failed to read data from server")
+        .body;
+
+    if is_login_page(&body) {
+        // The login page must not be served from the cache once the user logs in again.
+        cache::invalidate(&url).await;
+        return Err(session_expired());
+    }
+
+    Ok(body)
 }
 
-pub async fn load_courses(config: &config::Config) -> Result<Vec<GradescopeCourse>> {
-    let html = fetch(config, "/").await?;
-    let html = Html::parse_document(&html);
-    let selector = Selector::parse(".courseBox").unwrap();
-    let boxes = html.select(&selector);
-    Ok(boxes
-        .filter_map(|b| {
-            let id: i64 = b
+fn parse_course_box(b: ElementRef, term: Option<&str>, current: bool) -> Option<GradescopeCourse> {
+    let id: i64 = b
+        .value()
+        .attr("href")?
+        .strip_prefix("/courses/")?
+        .parse()
+        .ok()?;
+    let t: Vec<_> = b.text().collect();
+    if let [shortname, name, assignment_count] = &t[..] {
+        Some(GradescopeCourse {
+            shortname: shortname.to_string(),
+            name: name.to_string(),
+            assignment_count: assignment_count.split_whitespace().next()?.parse().ok()?,
+            id,
+            term: term.map(|x| x.to_string()),
+            current,
+        })
+    } else {
+        None
+    }
+}
+
+/// Reads the courses on the dashboard, which lists them under term headings, newest first.
+fn parse_dashboard(html: &Html) -> Vec<GradescopeCourse> {
+    let course_box = Selector::parse(".courseBox").unwrap();
+    let list = Selector::parse(".courseList").unwrap();
+
+    let mut courses = vec![];
+    for list in html.select(&list) {
+        let mut term = None;
+        let mut first_term = true;
+        for child in list.child_elements() {
+            if child
                 .value()
-                .attr("href")?
-                .strip_prefix("/courses/")?
-                .parse()
-                .ok()?;
-            let t: Vec<_> = b.text().collect();
-            if let [shortname, name, assignment_count] = &t[..] {
-                Some(GradescopeCourse {
-                    shortname: shortname.to_string(),
-                    name: name.to_string(),
-                    assignment_count: assignment_count.split_whitespace().next()?.parse().ok()?,
-                    id,
-                })
+                .has_class("courseList--term", CaseSensitivity::CaseSensitive)
+            {
+                if term.is_some() {
+                    first_term = false;
+                }
+                term = Some(child.text().collect::<String>().trim().to_string());
             } else {
-                None
+                courses.extend(
+                    child
+                        .select(&course_box)
+                        .filter_map(|b| parse_course_box(b, term.as_deref(), first_term)),
+                );
             }
+        }
+    }
+
+    // Without term headings, every course is treated as current.
+    if courses.is_empty() {
+        courses = html
+            .select(&course_box)
+            .filter_map(|b| parse_course_box(b, None, true))
+            .collect();
+    }
+
+    courses
+}
+
+/// Loads the courses on the dashboard that have not been excluded, from every term.
+pub async fn load_courses(config: &config::Config) -> Result<Vec<GradescopeCourse>> {
+    let html = fetch(config, "/").await?;
+
+    // A logged out dashboard has no course list at all, rather than an empty one.
+    let courses = {
+        let html = Html::parse_document(&html);
+        let wrapper = Selector::parse(".courseList, .courseBox").unwrap();
+        html.select(&wrapper)
+            .next()
+            .is_some()
+            .then(|| parse_dashboard(&html))
+    };
+    let Some(courses) = courses else {
+        cache::invalidate(&gradescope_url(config, "/")).await;
+        return Err(session_expired());
+    };
+
+    Ok(courses
+        .into_iter()
+        .filter(|x| {
+            !config.exclude.iter().any(|y| match y {
+                Exclusion::ByGradescopeCourseId {
+                    gradescope_course_id,
+                } => gradescope_course_id == &x.id,
+                _ => false,
+            })
         })
         .collect())
 }
 
+/// Whether assignments should be loaded for the course: either its term is listed in
+/// `gradescope_terms`, or no terms are listed and it is in the current term.
+pub fn is_selected(config: &config::Config, course: &GradescopeCourse) -> bool {
+    match &config.gradescope_terms {
+        Some(terms) => course
+            .term
+            .as_ref()
+            .is_some_and(|term| terms.iter().any(|x| x.eq_ignore_ascii_case(term.trim()))),
+        None => course.current,
+    }
+}
+
+/// The assignment id in a link such as `/courses/1/assignments/2/submissions/3`.
+fn assignment_id_from_link(link: &str) -> Option<i64> {
+    let mut segments = link.split('/').skip_while(|x| *x != "assignments");
+    segments.nth(1)?.parse().ok()
+}
+
+fn fallback_id(course_id: i64, name: &str) -> i64 {
+    -((stable_hash(&format!("{}:{}", course_id, name.trim())) >> 12) as i64)
+}
+
+fn parse_timestamp(s: &str) -> Option<DateTime<Local>> {
+    DateTime::parse_from_str(s.trim(), "%Y-%m-%d %H:%M:%S %z")
+        .ok()
+        .map(|x| x.into())
+}
+
+/// Release date, due date and late due date of a row in the course's assignment table.
+///
+/// Newer pages mark each date with a `time` element and a class. Older ones only have hidden
+/// timestamps, in that order, with the late due date left out when there is none.
+fn parse_dates(row: ElementRef, texts: &[&str]) -> [Option<DateTime<Local>>; 3] {
+    let selector = Selector::parse("time[datetime]").unwrap();
+    let mut dates = [None; 3];
+    let mut found = false;
+    for time in row.select(&selector) {
+        let Some(date) = time.value().attr("datetime").and_then(parse_timestamp) else {
+            continue;
+        };
+        let text: String = time.text().collect();
+        let class = |x: &str| time.value().has_class(x, CaseSensitivity::CaseSensitive);
+        let slot = if class("submissionTimeChart--releaseDate") {
+            0
+        } else if text.to_lowercase().contains("late") || dates[1].is_some() {
+            2
+        } else if class("submissionTimeChart--dueDate") {
+            1
+        } else {
+            continue;
+        };
+        dates[slot] = Some(date);
+        found = true;
+    }
+    if found {
+        return dates;
+    }
+
+    let timestamps: Vec<_> = texts.iter().filter_map(|x| parse_timestamp(x)).collect();
+    match timestamps[..] {
+        [due] => [None, Some(due), None],
+        [release, due] => [Some(release), Some(due), None],
+        [release, due, late_due, ..] => [Some(release), Some(due), Some(late_due)],
+        [] => [None; 3],
+    }
+}
+
+/// Parses a score such as "8.5 / 10.0".
+fn parse_score(text: &str) -> Option<(f64, f64)> {
+    let (score, max) = text.split_once('/')?;
+    Some((score.trim().parse().ok()?, max.trim().parse().ok()?))
+}
+
 pub async fn load_assignments_for_course(
     config: &config::Config,
     id: i64,
@@ -89,14 +379,29 @@ pub async fn load_assignments_for_course(
             let selector = Selector::parse("a").unwrap();
             let link = b.select(&selector).next();
             let link = link.and_then(|x| x.value().attr("href"));
+            // Assignments without a submission have a submit button instead of a link.
+            let selector = Selector::parse("[data-assignment-id]").unwrap();
+            let button_id = b
+                .select(&selector)
+                .next()
+                .and_then(|x| x.value().attr("data-assignment-id")?.parse().ok());
+            let name = texts.first()?.to_string();
+            let [release_at, due_at, late_due_at] = parse_dates(b, &texts);
+            let score_selector = Selector::parse(".submissionStatus--score").unwrap();
 
             Some(GradescopeAssignment {
-                due_at: texts.iter().rev().find_map(|t| {
-                    let due_at = DateTime::parse_from_str(t, "%Y-%m-%d %H:%M:%S %z");
-                    let due_at = due_at.ok()?;
-                    Some(due_at.into())
-                }),
-                name: texts.first()?.to_string(),
+                id: link
+                    .and_then(assignment_id_from_link)
+                    .or(button_id)
+                    .unwrap_or_else(|| fallback_id(id, &name)),
+                release_at,
+                due_at,
+                late_due_at,
+                name,
+                score: b
+                    .select(&score_selector)
+                    .next()
+                    .and_then(|x| parse_score(&x.text().collect::<String>())),
                 submitted: texts
                     .iter()
                     .find_map(|&x| {
@@ -108,13 +413,963 @@ pub async fn load_assignments_for_course(
                             None
                         }
                     })
-                    .unwrap_or_else(|| {
-                        let selector = Selector::parse(".submissionStatus--score").unwrap();
-                        let mut score = b.select(&selector);
-                        score.next().is_some()
-                    }),
+                    .unwrap_or_else(|| b.select(&score_selector).next().is_some()),
                 link: link.map(|x| x.to_string()),
             })
         })
         .collect())
 }
+
+pub struct GradescopeSource;
+
+#[async_trait]
+impl AssignmentSource for GradescopeSource {
+    fn name(&self) -> &'static str {
+        "Gradescope"
+    }
+
+    async fn load(&self, progress: &Progress, config: &config::Config) -> Result<Loaded> {
+        if config.gradescope_cookie.is_none() {
+            return Ok(Loaded::default());
+        }
+
+        let mut courses = progress
+            .wrap("Loading Gradescope courses", load_courses(config))
+            .await?;
+        courses.retain(|x| is_selected(config, x));
+
+        let results = join_all(courses.into_iter().map(|course| async move {
+            progress
+                .wrap(
+                    &format!("Loading assignments for {}", course.name),
+                    load_assignments_for_course(config, course.id),
+                )
+                .await
+                .map(|assignments| {
+                    assignments
+                        .into_iter()
+                        .map(|x| to_item(config, &course, x))
+                        .collect()
+                })
+                .map_err(|error| Failure {
+                    source: format!("Gradescope course {}", course.name),
+                    error,
+                })
+        }))
+        .await;
+
+        Ok(Loaded::from_courses(results))
+    }
+}
+
+fn to_item(
+    config: &config::Config,
+    course: &GradescopeCourse,
+    assignment: GradescopeAssignment,
+) -> Item {
+    Item {
+        key: format!("gradescope:assignment:{}", assignment.id),
+        kind: ItemKind::Assignment,
+        course: Course {
+            key: format!("gradescope:course:{}", course.id),
+            name: course.name.clone(),
+        },
+        title: assignment.name.trim().to_string(),
+        due_at: assignment.due_at,
+        late_due_at: assignment.late_due_at,
+        status: if assignment.submitted {
+            Status::Submitted
+        } else {
+            Status::Pending
+        },
+        points: assignment.score.map(|(_, max)| max),
+        url: match assignment.link {
+            Some(link) => gradescope_url(config, &link).to_string(),
+            None => gradescope_url(config, &format!("/courses/{}", course.id)).to_string(),
+        },
+        submission_kinds: vec![SubmissionKind::Gradescope],
+        locked: assignment.release_at.is_some_and(|x| x > Local::now()),
+        other_urls: vec![],
+        other_keys: vec![],
+    }
+}
+
+pub async fn run_courses(config: &config::Config) -> Result<()> {
+    require_login(config)?;
+
+    let courses = load_courses(config).await?;
+    let width = courses
+        .iter()
+        .map(|x| x.shortname.trim().len())
+        .max()
+        .unwrap_or(0);
+
+    let mut term = None;
+    for course in &courses {
+        if term != Some(&course.term) {
+            if term.is_some() {
+                println!();
+            }
+            let heading = course.term.as_deref().unwrap_or("No term");
+            if is_selected(config, course) {
+                println!("{} {}", heading.bold(), "(loaded)".green());
+            } else {
+                println!("{}", heading.bold());
+            }
+            term = Some(&course.term);
+        }
+        println!(
+            "  {:width$}  {} {}",
+            course.shortname.trim(),
+            course.name.trim(),
+            format!(
+                "({} assignment{}, id {})",
+                course.assignment_count,
+                if course.assignment_count == 1 {
+                    ""
+                } else {
+                    "s"
+                },
+                course.id
+            )
+            .bright_black(),
+            width = width
+        );
+    }
+
+    Ok(())
+}
+
+/// Loads the assignments of every selected course, for views that show all of them at once.
+pub async fn load_selected(
+    config: &config::Config,
+) -> Result<Vec<(GradescopeCourse, Vec<GradescopeAssignment>)>> {
+    let mut courses = load_courses(config).await?;
+    courses.retain(|x| is_selected(config, x));
+    try_join_all(courses.into_iter().map(|course| async move {
+        let assignments = load_assignments_for_course(config, course.id).await?;
+        Ok((course, assignments))
+    }))
+    .await
+}
+
+/// Finds the Gradescope assignment an item argument refers to.
+pub async fn lookup_assignment(
+    config: &config::Config,
+    query: &str,
+) -> Result<(GradescopeCourse, GradescopeAssignment)> {
+    require_login(config)?;
+
+    let (id, course_id, name) = match handle::resolve(config, query).await? {
+        Target::Key {
+            key, course_key, ..
+        } => (
+            Some(
+                gradescope_id(&key, "assignment")
+                    .ok_or_else(|| eyre!("`{}` is not a Gradescope assignment", query))?,
+            ),
+            course_key.and_then(|x| gradescope_id(&x, "course")),
+            None,
+        ),
+        Target::Id(id) => (Some(id), None, None),
+        Target::Name(name) => (None, None, Some(name.to_lowercase())),
+    };
+
+    let progress = Progress::new();
+    let courses = progress
+        .wrap("Loading Gradescope courses", load_courses(config))
+        .await;
+    let courses: Vec<_> = courses?
+        .into_iter()
+        .filter(|x| match course_id {
+            Some(id) => x.id == id,
+            None => is_selected(config, x),
+        })
+        .collect();
+    let loaded = {
+        let progress = &progress;
+        try_join_all(courses.iter().map(|course| async move {
+            progress
+                .wrap(
+                    &format!("Loading assignments for {}", course.name),
+                    load_assignments_for_course(config, course.id),
+                )
+                .await
+        }))
+        .await
+    };
+    progress.finish();
+
+    let mut matches = vec![];
+    for (course, assignments) in courses.iter().zip(loaded?) {
+        for assignment in assignments {
+            let found = match (&id, &name) {
+                (Some(id), _) => assignment.id == *id,
+                (_, Some(name)) => assignment.name.to_lowercase().contains(name),
+                _ => false,
+            };
+            if found {
+                matches.push((course.clone(), assignment));
+            }
+        }
+    }
+
+    match matches.len() {
+        1 => Ok(matches.remove(0)),
+        0 => Err(eyre!("No Gradescope assignment matches `{}`", query)),
+        _ => Err(eyre!("`{}` matches more than one assignment", query)).suggestion(format!(
+            "Did you mean one of: {}",
+            matches
+                .iter()
+                .map(|(course, x)| format!(
+                    "{} ({}, {})",
+                    x.name.trim(),
+                    course.shortname.trim(),
+                    x.id
+                ))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+/// Looks through the data the submission page hands to its scripts for autograder results.
+fn find_autograder_results(props: &Value) -> Option<AutograderResults> {
+    match props {
+        Value::Object(map) => {
+            if let Some(results) = map.get("autograder_results").filter(|x| x.is_object()) {
+                return serde_json::from_value(results.clone()).ok();
+            }
+            if map.get("tests").is_some_and(|x| x.is_array()) {
+                return serde_json::from_value(props.clone()).ok();
+            }
+            map.values().find_map(find_autograder_results)
+        }
+        Value::Array(values) => values.iter().find_map(find_autograder_results),
+        _ => None,
+    }
+}
+
+fn parse_autograder_results(html: &Html) -> Option<AutograderResults> {
+    let selector = Selector::parse("[data-react-props]").unwrap();
+    html.select(&selector).find_map(|x| {
+        let props: Value = serde_json::from_str(x.value().attr("data-react-props")?).ok()?;
+        find_autograder_results(&props)
+    })
+}
+
+fn format_points(score: Option<f64>, max: Option<f64>) -> String {
+    match (score, max) {
+        (Some(score), Some(max)) => format!("{} / {}", score, max),
+        (Some(score), None) => score.to_string(),
+        (None, Some(max)) => format!("- / {}", max),
+        (None, None) => "-".into(),
+    }
+}
+
+/// The link to the user's submission, which assignments without one do not have.
+fn submission_link(assignment: &GradescopeAssignment) -> Option<&String> {
+    assignment
+        .link
+        .as_ref()
+        .filter(|x| x.contains("/submissions/"))
+}
+
+pub async fn run_results(config: &config::Config, query: &str) -> Result<()> {
+    let (course, assignment) = lookup_assignment(config, query).await?;
+    let link = submission_link(&assignment)
+        .ok_or_else(|| eyre!("Nothing has been submitted to {}", assignment.name.trim()))?;
+
+    let html = fetch(config, link).await?;
+    let html = Html::parse_document(&html);
+
+    println!("{}", assignment.name.trim().bold().underline());
+    println!("{}", course.name);
+    println!();
+
+    let Some(results) = parse_autograder_results(&html) else {
+        if let Some((score, max)) = assignment.score {
+            println!("Score: {}", format_points(Some(score), Some(max)).bold());
+        }
+        println!(
+            "{}",
+            "No autograder results for this submission.".bright_black()
+        );
+        return Ok(());
+    };
+
+    for test in &results.tests {
+        let passed = match test.status.as_deref() {
+            Some("passed") => Some(true),
+            Some("failed") => Some(false),
+            _ => match (test.score, test.max_score) {
+                (Some(score), Some(max)) => Some(score >= max),
+                _ => None,
+            },
+        };
+        let mark = match passed {
+            Some(true) => "✓".green(),
+            Some(false) => "✗".red(),
+            None => "•".normal(),
+        };
+        let name = match (&test.number, &test.name) {
+            (Some(number), Some(name)) => format!("{} {}", number, name),
+            (None, Some(name)) => name.clone(),
+            (Some(number), None) => number.clone(),
+            (None, None) => "Test".into(),
+        };
+        println!(
+            "{} {} {}",
+            mark,
+            name,
+            format!("({})", format_points(test.score, test.max_score)).bright_black()
+        );
+        if passed == Some(false) {
+            if let Some(output) = test.output.as_deref().filter(|x| !x.trim().is_empty()) {
+                for line in output.trim().lines() {
+                    println!("    {}", line.bright_black());
+                }
+            }
+        }
+    }
+
+    if let Some(output) = results.output.as_deref().filter(|x| !x.trim().is_empty()) {
+        println!();
+        println!("{}", output.trim());
+    }
+
+    println!();
+    let max = results
+        .tests
+        .iter()
+        .map(|x| x.max_score)
+        .sum::<Option<f64>>()
+        .filter(|x| *x > 0.0)
+        .or(assignment.score.map(|(_, max)| max));
+    println!(
+        "Total: {}",
+        format_points(
+            results.score.or(assignment.score.map(|(score, _)| score)),
+            max
+        )
+        .bold()
+    );
+
+    Ok(())
+}
+
+pub async fn run_login(config: &config::Config, email: Option<String>) -> Result<()> {
+    let email = match email {
+        Some(email) => email,
+        None => {
+            eprint!("Email: ");
+            let mut email = String::new();
+            std::io::stdin().read_line(&mut email)?;
+            email.trim().to_string()
+        }
+    };
+    let password = rpassword::prompt_password("Password: ")?;
+
+    let login_url = gradescope_url(config, "/login");
+    let mut cookies = BTreeMap::new();
+
+    let response = LOGIN_CLIENT
+        .get(login_url.clone())
+        .send()
+        .await
+        .wrap_err("Unable to load the Gradescope login page")?
+        .error_for_status()
+        .wrap_err("Server returned error")?;
+    update_cookies(&mut cookies, &response);
+    let html = Html::parse_document(&response.text().await?);
+    let selector = Selector::parse("form input[name=authenticity_token]").unwrap();
+    let token = html
+        .select(&selector)
+        .next()
+        .and_then(|x| x.value().attr("value"))
+        .ok_or_else(|| eyre!("Unable to find the login form on Gradescope"))?
+        .to_string();
+
+    let response = LOGIN_CLIENT
+        .post(login_url)
+        .header("Cookie", cookie_header(&cookies))
+        .form(&[
+            ("utf8", "✓"),
+            ("authenticity_token", &token),
+            ("session[email]", &email),
+            ("session[password]", &password),
+            ("session[remember_me]", "1"),
+            ("commit", "Log In"),
+            ("session[remember_me_sso]", "0"),
+        ])
+        .send()
+        .await
+        .wrap_err("Unable to log in to Gradescope")?;
+    update_cookies(&mut cookies, &response);
+
+    // A successful login redirects to the account page; a failed one shows the form again.
+    let location = response
+        .headers()
+        .get(LOCATION)
+        .and_then(|x| x.to_str().ok())
+        .unwrap_or_default();
+    if !response.status().is_redirection() || location.contains("/login") {
+        return Err(eyre!("Gradescope did not accept the email and password"))
+            .suggestion("Accounts that sign in through a school need `gradescope_cookie` instead");
+    }
+
+    save_session(&cookies).await?;
+    cache::invalidate(&gradescope_url(config, "/")).await;
+    println!("Logged in to Gradescope as {}.", email);
+
+    Ok(())
+}
+
+/// A file linked from a submission page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Download {
+    url: Url,
+    /// Whether this is the graded copy, as opposed to what was originally submitted.
+    graded: bool,
+}
+
+/// Finds the graded copy and the original submission among the links on a submission page.
+fn parse_downloads(config: &config::Config, html: &Html) -> Vec<Download> {
+    let selector = Selector::parse("a[href]").unwrap();
+    let mut downloads = vec![];
+    for link in html.select(&selector) {
+        let href = link.value().attr("href").unwrap_or_default();
+        if !href.contains("/submissions/") {
+            continue;
+        }
+        let text = link.text().collect::<String>().to_lowercase();
+        let graded = href.ends_with(".pdf") || text.contains("graded");
+        let original = href.contains("zip_download")
+            || href.contains("original_file")
+            || (text.contains("download") && !graded);
+        if graded || original {
+            let download = Download {
+                url: gradescope_url(config, href),
+                graded,
+            };
+            if !downloads.contains(&download) {
+                downloads.push(download);
+            }
+        }
+    }
+    downloads
+}
+
+/// Makes a name safe to use as a file name.
+fn file_name(name: &str) -> String {
+    name.trim()
+        .chars()
+        .map(|x| match x {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '-',
+            x => x,
+        })
+        .collect()
+}
+
+/// The file name the server suggests, from `Content-Disposition`.
+fn suggested_name(response: &reqwest::Response) -> Option<String> {
+    let header = response.headers().get(CONTENT_DISPOSITION)?.to_str().ok()?;
+    let name = header
+        .split(';')
+        .find_map(|x| x.trim().strip_prefix("filename="))?
+        .trim_matches('"');
+    Some(name.to_string()).filter(|x| !x.is_empty())
+}
+
+/// Whether the file on disk matches what the server is about to send.
+async fn is_up_to_date(path: &Path, response: &reqwest::Response) -> bool {
+    let Ok(metadata) = fs::metadata(path).await else {
+        return false;
+    };
+    if response
+        .content_length()
+        .is_some_and(|x| x != metadata.len())
+    {
+        return false;
+    }
+    let last_modified = response
+        .headers()
+        .get(LAST_MODIFIED)
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| DateTime::parse_from_rfc2822(x).ok());
+    match (last_modified, metadata.modified()) {
+        (Some(last_modified), Ok(modified)) => {
+            DateTime::<Local>::from(modified) >= last_modified.with_timezone(&Local)
+        }
+        // Without a date to compare, a file of the right size is taken to be the same one.
+        _ => response.content_length().is_some(),
+    }
+}
+
+/// Where a download is written until it is complete. The suffix is appended rather than replacing
+/// the extension, since names like "Lab 2.1 - submission" would otherwise collide.
+fn partial_path(path: &Path) -> PathBuf {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".part");
+    PathBuf::from(partial)
+}
+
+/// Streams one file into `dir`, returning where it was saved, or `None` if it was up to date.
+async fn download_file(
+    config: &config::Config,
+    assignment: &GradescopeAssignment,
+    download: &Download,
+    dir: &Path,
+) -> Result<Option<PathBuf>> {
+    let mut response = request(config, &download.url)
+        .send()
+        .await
+        .wrap_err_with(|| eyre!("Unable to download {}", download.url))?
+        .error_for_status()
+        .wrap_err("Server returned error")?;
+
+    let name = if download.graded {
+        format!("{} - graded.pdf", file_name(&assignment.name))
+    } else {
+        match suggested_name(&response) {
+            Some(suggested) => format!(
+                "{} - {}",
+                file_name(&assignment.name),
+                file_name(&suggested)
+            ),
+            None => format!("{} - submission", file_name(&assignment.name)),
+        }
+    };
+    let path = dir.join(name);
+    if is_up_to_date(&path, &response).await {
+        return Ok(None);
+    }
+
+    // Written next to the destination first, so an interrupted download leaves the old file.
+    let partial = partial_path(&path);
+    let mut file = fs::File::create(&partial)
+        .await
+        .wrap_err_with(|| eyre!("Unable to create {}", partial.display()))?;
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .wrap_err("Failed to read data from server")?
+    {
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+    fs::rename(&partial, &path).await?;
+
+    Ok(Some(path))
+}
+
+/// Downloads the files of one assignment, printing what happened to each.
+async fn download_assignment(
+    config: &config::Config,
+    progress: &Progress,
+    assignment: &GradescopeAssignment,
+    dir: &Path,
+) -> Result<()> {
+    let link = submission_link(assignment)
+        .ok_or_else(|| eyre!("Nothing has been submitted to {}", assignment.name.trim()))?;
+
+    let page = progress
+        .wrap(
+            &format!("Loading {}", assignment.name.trim()),
+            fetch(config, link),
+        )
+        .await?;
+    let downloads = parse_downloads(config, &Html::parse_document(&page));
+
+    // Only the graded copy is wanted once there is one.
+    let downloads: Vec<_> = if downloads.iter().any(|x| x.graded) {
+        downloads.into_iter().filter(|x| x.graded).collect()
+    } else {
+        downloads
+    };
+
+    for download in &downloads {
+        let saved = progress
+            .wrap(
+                &format!("Downloading {}", assignment.name.trim()),
+                download_file(config, assignment, download, dir),
+            )
+            .await?;
+        match saved {
+            Some(path) => println!("Saved {}", path.display()),
+            None => println!(
+                "{}",
+                format!("{} is up to date", assignment.name.trim()).bright_black()
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+/// Finds a Gradescope course by id, or by a case-insensitive match on its name or short name.
+fn find_course<'a>(courses: &'a [GradescopeCourse], query: &str) -> Result<&'a GradescopeCourse> {
+    if let Some(course) = query
+        .parse::<i64>()
+        .ok()
+        .and_then(|id| courses.iter().find(|x| x.id == id))
+    {
+        return Ok(course);
+    }
+
+    let needle = query.to_lowercase();
+    let matches: Vec<_> = courses
+        .iter()
+        .filter(|x| {
+            x.name.to_lowercase().contains(&needle) || x.shortname.to_lowercase().contains(&needle)
+        })
+        .collect();
+
+    match &matches[..] {
+        [course] => Ok(course),
+        [] => Err(eyre!("No Gradescope course matches `{}`", query))
+            .suggestion("Run `canvas gradescope courses` to see them"),
+        _ => Err(eyre!("`{}` matches more than one course", query)).suggestion(format!(
+            "Did you mean one of: {}",
+            matches
+                .iter()
+                .map(|x| format!("{} ({})", x.shortname.trim(), x.id))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+pub async fn run_download(
+    config: &config::Config,
+    assignment: Option<String>,
+    course: Option<String>,
+    dir: &Path,
+) -> Result<()> {
+    require_login(config)?;
+
+    let whole_course = assignment.is_none();
+    let (dir, assignments) = match (assignment, course) {
+        (Some(query), _) => {
+            let (_, assignment) = lookup_assignment(config, &query).await?;
+            (dir.to_path_buf(), vec![assignment])
+        }
+        (None, Some(query)) => {
+            let courses = load_courses(config).await?;
+            let course = find_course(&courses, &query)?;
+            let assignments = load_assignments_for_course(config, course.id).await?;
+            (dir.join(file_name(&course.name)), assignments)
+        }
+        (None, None) => return Err(eyre!("Give an assignment, or a course with --course")),
+    };
+
+    fs::create_dir_all(&dir)
+        .await
+        .wrap_err_with(|| eyre!("Unable to create {}", dir.display()))?;
+
+    let progress = Progress::new();
+    if !whole_course {
+        let result = download_assignment(config, &progress, &assignments[0], &dir).await;
+        progress.finish();
+        return result;
+    }
+
+    // One assignment failing should not keep the rest of the course from downloading.
+    let mut failed = 0;
+    for assignment in &assignments {
+        if submission_link(assignment).is_none() {
+            println!(
+                "{}",
+                format!(
+                    "Skipped {}, nothing has been submitted",
+                    assignment.name.trim()
+                )
+                .bright_black()
+            );
+            continue;
+        }
+        if let Err(error) = download_assignment(config, &progress, assignment, &dir).await {
+            failed += 1;
+            progress.println(&format!(
+                "{} {:#}",
+                format!("Unable to download {}:", assignment.name.trim())
+                    .yellow()
+                    .bold(),
+                error
+            ));
+        }
+    }
+    progress.finish();
+
+    if failed > 0 {
+        return Err(eyre!(
+            "{} of {} assignments failed to download",
+            failed,
+            assignments.len()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        matchers::{header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+    use crate::config::test_config;
+
+    const DASHBOARD: &str = include_str!("../tests/fixtures/gradescope/dashboard.html");
+    const LOGIN: &str = include_str!("../tests/fixtures/gradescope/login.html");
+    const COURSE: &str = include_str!("../tests/fixtures/gradescope/course.html");
+    const LEGACY_COURSE: &str = include_str!("../tests/fixtures/gradescope/course_legacy.html");
+
+    /// A Gradescope serving `page` at `route` to requests with the test session cookie.
+    async fn serve(route: &str, page: &str) -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(route))
+            .and(header("Cookie", "session"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(page))
+            .mount(&server)
+            .await;
+        server
+    }
+
+    fn at(s: &str) -> Option<DateTime<Local>> {
+        Some(parse_timestamp(s).unwrap())
+    }
+
+    fn row_dates(page: &str) -> Vec<[Option<DateTime<Local>>; 3]> {
+        let html = Html::parse_document(page);
+        let selector = Selector::parse("tbody > tr").unwrap();
+        html.select(&selector)
+            .map(|row| parse_dates(row, &row.text().collect::<Vec<_>>()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn courses_from_the_dashboard() {
+        let server = serve("/", DASHBOARD).await;
+        let courses = load_courses(&test_config(&server.uri())).await.unwrap();
+        let courses: Vec<_> = courses
+            .iter()
+            .map(|x| {
+                (
+                    x.id,
+                    x.shortname.as_str(),
+                    x.name.as_str(),
+                    x.assignment_count,
+                    x.term.as_deref(),
+                    x.current,
+                )
+            })
+            .collect();
+        assert_eq!(
+            courses,
+            vec![
+                (
+                    123,
+                    "CS 61A",
+                    "Structure and Interpretation of Computer Programs",
+                    12,
+                    Some("Fall 2024"),
+                    true
+                ),
+                (
+                    321,
+                    "Math 54",
+                    "Linear Algebra and Differential Equations",
+                    1,
+                    Some("Fall 2024"),
+                    true
+                ),
+                (
+                    99,
+                    "CS 10",
+                    "The Beauty and Joy of Computing",
+                    30,
+                    Some("Spring 2024"),
+                    false
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn login_page_is_an_expired_session() {
+        let server = serve("/", LOGIN).await;
+        let error = load_courses(&test_config(&server.uri())).await.unwrap_err();
+        assert_eq!(error.to_string(), session_expired().to_string());
+    }
+
+    #[tokio::test]
+    async fn assignments_from_the_course_page() {
+        let server = serve("/courses/123", COURSE).await;
+        let assignments = load_assignments_for_course(&test_config(&server.uri()), 123)
+            .await
+            .unwrap();
+        let assignments: Vec<_> = assignments
+            .iter()
+            .map(|x| {
+                (
+                    x.id,
+                    x.name.as_str(),
+                    x.submitted,
+                    x.score,
+                    x.link.as_deref(),
+                    x.due_at,
+                )
+            })
+            .collect();
+        assert_eq!(
+            assignments,
+            vec![
+                (
+                    456,
+                    "Homework 1",
+                    true,
+                    Some((8.5, 10.0)),
+                    Some("/courses/123/assignments/456/submissions/789"),
+                    at("2024-09-09 23:59:00 -0700"),
+                ),
+                (
+                    460,
+                    "Lab 2.1",
+                    true,
+                    None,
+                    Some("/courses/123/assignments/460/submissions/801"),
+                    at("2024-09-16 23:59:00 -0700"),
+                ),
+                (
+                    457,
+                    "Project 1",
+                    false,
+                    None,
+                    None,
+                    at("2024-09-26 23:59:00 -0700"),
+                ),
+            ]
+        );
+    }
+
+    /// A Gradescope with the dashboard and the course page, whose submissions fail to load.
+    async fn serve_course() -> MockServer {
+        let server = serve("/", DASHBOARD).await;
+        Mock::given(method("GET"))
+            .and(path("/courses/123"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(COURSE))
+            .mount(&server)
+            .await;
+        for submission in [
+            "/courses/123/assignments/456/submissions/789",
+            "/courses/123/assignments/460/submissions/801",
+        ] {
+            Mock::given(method("GET"))
+                .and(path(submission))
+                .respond_with(ResponseTemplate::new(500))
+                .mount(&server)
+                .await;
+        }
+        server
+    }
+
+    #[tokio::test]
+    async fn downloading_an_unsubmitted_assignment_is_an_error() {
+        let server = serve_course().await;
+        let config = test_config(&server.uri());
+        let dir = cache::cache_dir().join("unsubmitted");
+        let error = run_download(
+            &config,
+            Some(format!("{}/courses/123/assignments/457", server.uri())),
+            None,
+            &dir,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.to_string(), "Nothing has been submitted to Project 1");
+    }
+
+    #[tokio::test]
+    async fn downloading_a_course_continues_past_failures() {
+        let server = serve_course().await;
+        let config = test_config(&server.uri());
+        let dir = cache::cache_dir().join("course");
+        let error = run_download(&config, None, Some("123".into()), &dir)
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "2 of 3 assignments failed to download");
+        let requested: Vec<_> = server
+            .received_requests()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|x| x.url.path().to_string())
+            .filter(|x| x.contains("/submissions/"))
+            .collect();
+        assert_eq!(
+            requested,
+            vec![
+                "/courses/123/assignments/456/submissions/789",
+                "/courses/123/assignments/460/submissions/801",
+            ]
+        );
+    }
+
+    #[test]
+    fn partial_downloads_keep_the_whole_name() {
+        assert_eq!(
+            partial_path(Path::new("out/Lab 2.1 - submission")),
+            Path::new("out/Lab 2.1 - submission.part")
+        );
+        assert_eq!(
+            partial_path(Path::new("out/Lab 2.2 - graded.pdf")),
+            Path::new("out/Lab 2.2 - graded.pdf.part")
+        );
+    }
+
+    #[test]
+    fn dates_from_time_elements() {
+        assert_eq!(
+            row_dates(COURSE),
+            vec![
+                [
+                    at("2024-09-02 09:00:00 -0700"),
+                    at("2024-09-09 23:59:00 -0700"),
+                    at("2024-09-11 23:59:00 -0700"),
+                ],
+                [
+                    at("2024-09-10 09:00:00 -0700"),
+                    at("2024-09-16 23:59:00 -0700"),
+                    None,
+                ],
+                [
+                    at("2024-09-12 09:00:00 -0700"),
+                    at("2024-09-26 23:59:00 -0700"),
+                    at("2024-09-28 23:59:00 -0700"),
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn dates_from_hidden_timestamps() {
+        assert_eq!(
+            row_dates(LEGACY_COURSE),
+            vec![
+                [
+                    at("2024-09-03 08:00:00 -0700"),
+                    at("2024-09-05 23:59:00 -0700"),
+                    at("2024-09-06 23:59:00 -0700"),
+                ],
+                [
+                    at("2024-09-10 08:00:00 -0700"),
+                    at("2024-09-12 23:59:00 -0700"),
+                    None,
+                ],
+            ]
+        );
+    }
+}
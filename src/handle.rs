@@ -0,0 +1,404 @@
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result, Section,
+};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::{
+    cache::{cache_dir, stable_hash},
+    config,
+    source::Item,
+};
+
+/// An item as shown by the last `todo`, so later commands can refer to it without a refetch.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Listed {
+    pub key: String,
+    pub course_key: String,
+    pub course_name: String,
+    pub title: String,
+    pub url: String,
+}
+
+impl From<&Item> for Listed {
+    fn from(item: &Item) -> Self {
+        Listed {
+            key: item.key.clone(),
+            course_key: item.course.key.clone(),
+            course_name: item.course.name.clone(),
+            title: item.title.clone(),
+            url: item.url.clone(),
+        }
+    }
+}
+
+/// What an item argument refers to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// An item from the last listing, or one named by its URL.
+    Key {
+        key: String,
+        course_key: Option<String>,
+        url: Option<String>,
+    },
+    /// A bare number that is not an index in the last listing.
+    Id(i64),
+    /// A name that matched nothing in the last listing.
+    Name(String),
+}
+
+/// A short hash of the key, which stays the same as long as the item exists.
+pub fn short_hash(key: &str) -> String {
+    format!("{:06x}", stable_hash(key) & 0xff_ffff)
+}
+
+/// The short hash as it has to be typed. One made only of digits could be mistaken for an id, so
+/// it gets an `@` in front.
+pub fn hash_handle(key: &str) -> String {
+    let hash = short_hash(key);
+    if hash.chars().all(|x| x.is_ascii_digit()) {
+        format!("@{}", hash)
+    } else {
+        hash
+    }
+}
+
+/// The handles shown next to an item: its position in the listing and its short hash.
+pub fn format_handle(index: usize, key: &str) -> String {
+    format!("#{} {}", index, hash_handle(key))
+}
+
+fn listing_path() -> std::path::PathBuf {
+    cache_dir().join("last-listing.json")
+}
+
+pub async fn save_listing(listing: &[Listed]) -> Result<()> {
+    fs::create_dir_all(cache_dir()).await?;
+    fs::write(listing_path(), serde_json::to_vec(listing)?)
+        .await
+        .wrap_err("Unable to save listing")
+}
+
+async fn load_listing() -> Vec<Listed> {
+    match fs::read(listing_path()).await {
+        Ok(data) => serde_json::from_slice(&data).unwrap_or_default(),
+        Err(_) => vec![],
+    }
+}
+
+/// The id in a key such as `canvas:assignment:123`, if the key is of that type.
+pub fn canvas_id(key: &str, kind: &str) -> Option<i64> {
+    key_id(key, "canvas", kind)
+}
+
+/// The id in a key such as `gradescope:course:123`, if the key is of that type.
+pub fn gradescope_id(key: &str, kind: &str) -> Option<i64> {
+    key_id(key, "gradescope", kind)
+}
+
+fn key_id(key: &str, source: &str, kind: &str) -> Option<i64> {
+    key.strip_prefix(source)?
+        .strip_prefix(':')?
+        .strip_prefix(kind)?
+        .strip_prefix(':')?
+        .parse()
+        .ok()
+}
+
+fn listed_target(item: &Listed) -> Target {
+    Target::Key {
+        key: item.key.clone(),
+        course_key: Some(item.course_key.clone()),
+        url: Some(item.url.clone()),
+    }
+}
+
+fn ambiguous(query: &str, matches: &[&Listed]) -> color_eyre::Report {
+    eyre!("`{}` matches more than one item", query)
+        .suggestion(format!(
+            "Did you mean one of: {}",
+            matches
+                .iter()
+                .map(|x| format!("{} ({})", x.title, hash_handle(&x.key)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))
+        .note("Use the short hash shown by `canvas todo` to pick one")
+}
+
+/// Resolves an item argument, trying in order: an index from the last listing (`3` or `#3`), a
+/// short hash, a URL, a raw id, and finally the words of a name.
+pub async fn resolve(config: &config::Config, query: &str) -> Result<Target> {
+    resolve_in(config, &load_listing().await, query)
+}
+
+fn resolve_in(config: &config::Config, listing: &[Listed], query: &str) -> Result<Target> {
+    let query = query.trim();
+
+    if let Ok(index) = query.trim_start_matches('#').parse::<usize>() {
+        if (1..=listing.len()).contains(&index) {
+            return Ok(listed_target(&listing[index - 1]));
+        }
+    }
+
+    // Canvas ids are numbers too, so a hash made only of digits needs its `@`.
+    let lowercase = query.to_lowercase();
+    let (hash, explicit) = match lowercase.strip_prefix('@') {
+        Some(hash) => (hash, true),
+        None => (lowercase.as_str(), false),
+    };
+    if hash.len() >= 4
+        && hash.chars().all(|x| x.is_ascii_hexdigit())
+        && (explicit || hash.parse::<i64>().is_err())
+    {
+        let matches: Vec<_> = listing
+            .iter()
+            .filter(|x| short_hash(&x.key).starts_with(hash))
+            .collect();
+        match &matches[..] {
+            [item] => return Ok(listed_target(item)),
+            [] if explicit => {
+                return Err(eyre!("No item in the last listing has the hash `{}`", hash))
+                    .suggestion("Run `canvas todo` to see the current hashes")
+            }
+            [] => {}
+            _ => return Err(ambiguous(query, &matches)),
+        }
+    }
+
+    if query.starts_with("http://") || query.starts_with("https://") {
+        if let Some(item) = listing.iter().find(|x| x.url == query) {
+            return Ok(listed_target(item));
+        }
+        return parse_url(config, query);
+    }
+
+    if let Ok(id) = query.parse() {
+        return Ok(Target::Id(id));
+    }
+
+    let words: Vec<_> = lowercase.split_whitespace().collect();
+    let matches: Vec<_> = listing
+        .iter()
+        .filter(|x| {
+            let title = x.title.to_lowercase();
+            words.iter().all(|word| title.contains(word))
+        })
+        .collect();
+    match &matches[..] {
+        [item] => Ok(listed_target(item)),
+        [] => Ok(Target::Name(query.to_string())),
+        _ => Err(ambiguous(query, &matches)),
+    }
+}
+
+/// Understands links to Canvas courses, assignments, quizzes and discussions, and to Gradescope
+/// courses and assignments.
+fn parse_url(config: &config::Config, url: &str) -> Result<Target> {
+    let parsed = Url::parse(url).wrap_err_with(|| eyre!("`{}` is not a valid URL", url))?;
+    let segments: Vec<_> = parsed
+        .path_segments()
+        .map(|x| x.collect())
+        .unwrap_or_default();
+
+    let gradescope_host = Url::parse(&config.gradescope_url)
+        .ok()
+        .and_then(|x| x.host_str().map(|x| x.to_string()));
+    if parsed.host_str().is_some_and(|x| {
+        Some(x) == gradescope_host.as_deref() || x.split('.').any(|x| x == "gradescope")
+    }) {
+        return match segments[..] {
+            ["courses", course_id, "assignments", id, ..] => {
+                match (course_id.parse::<i64>(), id.parse::<i64>()) {
+                    (Ok(course_id), Ok(id)) => Ok(Target::Key {
+                        key: format!("gradescope:assignment:{}", id),
+                        course_key: Some(format!("gradescope:course:{}", course_id)),
+                        url: Some(url.to_string()),
+                    }),
+                    _ => Err(eyre!("Unrecognized link `{}`", url)),
+                }
+            }
+            ["courses", course_id, ..] => match course_id.parse::<i64>() {
+                Ok(course_id) => Ok(Target::Key {
+                    key: format!("gradescope:course:{}", course_id),
+                    course_key: None,
+                    url: Some(url.to_string()),
+                }),
+                Err(_) => Err(eyre!("Unrecognized link `{}`", url)),
+            },
+            _ => Err(eyre!("Unrecognized link `{}`", url))
+                .suggestion("Pass a link to a Gradescope course or assignment"),
+        };
+    }
+
+    if let ["courses", course_id] | ["courses", course_id, ""] = segments[..] {
+        if let Ok(course_id) = course_id.parse::<i64>() {
+            return Ok(Target::Key {
+                key: format!("canvas:course:{}", course_id),
+                course_key: None,
+                url: Some(url.to_string()),
+            });
+        }
+    }
+
+    if let ["courses", course_id, kind, id, ..] = segments[..] {
+        let kind = match kind {
+            "assignments" => Some("assignment"),
+            "quizzes" => Some("quiz"),
+            "discussion_topics" => Some("discussion_topic"),
+            _ => None,
+        };
+        if let (Some(kind), Ok(course_id), Ok(id)) =
+            (kind, course_id.parse::<i64>(), id.parse::<i64>())
+        {
+            return Ok(Target::Key {
+                key: format!("canvas:{}:{}", kind, id),
+                course_key: Some(format!("canvas:course:{}", course_id)),
+                url: Some(url.to_string()),
+            });
+        }
+    }
+
+    Err(eyre!("Unrecognized link `{}`", url))
+        .suggestion("Pass a link to a Canvas course, assignment, quiz or discussion")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_config;
+
+    fn config() -> config::Config {
+        let mut config = test_config("https://canvas.example.edu");
+        config.gradescope_url = "https://www.gradescope.ca/".into();
+        config
+    }
+
+    fn listed(key: &str, title: &str) -> Listed {
+        Listed {
+            key: key.into(),
+            course_key: "canvas:course:52817".into(),
+            course_name: "CS 61A".into(),
+            title: title.into(),
+            url: format!("https://canvas.example.edu/{}", key.replace(':', "/")),
+        }
+    }
+
+    fn key(target: Target) -> String {
+        match target {
+            Target::Key { key, .. } => key,
+            other => panic!("expected a listed item, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn indexes_hashes_and_names() {
+        // One key whose hash has a letter in it, and one whose hash is all digits.
+        let lettered = (1..)
+            .map(|x| format!("canvas:assignment:{}", x))
+            .find(|x| short_hash(x).chars().any(|x| x.is_ascii_alphabetic()))
+            .unwrap();
+        let numeric = (1..)
+            .map(|x| format!("canvas:assignment:{}", x))
+            .find(|x| short_hash(x).chars().all(|x| x.is_ascii_digit()))
+            .unwrap();
+        let listing = vec![
+            listed(&lettered, "Homework 1"),
+            listed(&numeric, "Homework 2"),
+            listed("canvas:quiz:7", "Lab 1 quiz"),
+        ];
+        let resolve = |query: &str| resolve_in(&config(), &listing, query);
+
+        assert_eq!(key(resolve("2").unwrap()), numeric);
+        assert_eq!(key(resolve("#3").unwrap()), "canvas:quiz:7");
+        assert_eq!(key(resolve(&short_hash(&lettered)).unwrap()), lettered);
+        assert_eq!(key(resolve(&hash_handle(&numeric)).unwrap()), numeric);
+        assert_eq!(hash_handle(&numeric), format!("@{}", short_hash(&numeric)));
+
+        // Without the `@`, a number is an id even if it happens to start a hash.
+        let digits = &short_hash(&numeric)[..5];
+        assert_eq!(
+            resolve(digits).unwrap(),
+            Target::Id(digits.parse().unwrap())
+        );
+        let unused = ["0000", "1111", "2222"]
+            .iter()
+            .copied()
+            .find(|hash| {
+                listing
+                    .iter()
+                    .all(|x| !short_hash(&x.key).starts_with(hash))
+            })
+            .unwrap();
+        assert!(resolve(&format!("@{}", unused)).is_err());
+
+        assert_eq!(key(resolve("lab quiz").unwrap()), "canvas:quiz:7");
+        let error = resolve("homework").unwrap_err();
+        assert_eq!(error.to_string(), "`homework` matches more than one item");
+        assert_eq!(resolve("midterm").unwrap(), Target::Name("midterm".into()));
+    }
+
+    #[test]
+    fn canvas_links() {
+        let config = config();
+        assert_eq!(
+            parse_url(
+                &config,
+                "https://canvas.example.edu/courses/52817/quizzes/7?module_item_id=1"
+            )
+            .unwrap(),
+            Target::Key {
+                key: "canvas:quiz:7".into(),
+                course_key: Some("canvas:course:52817".into()),
+                url: Some(
+                    "https://canvas.example.edu/courses/52817/quizzes/7?module_item_id=1".into()
+                ),
+            }
+        );
+        assert_eq!(
+            key(parse_url(&config, "https://canvas.example.edu/courses/52817/").unwrap()),
+            "canvas:course:52817"
+        );
+        assert_eq!(
+            key(parse_url(
+                &config,
+                "https://canvas.example.edu/courses/52817/discussion_topics/9"
+            )
+            .unwrap()),
+            "canvas:discussion_topic:9"
+        );
+        assert!(parse_url(&config, "https://canvas.example.edu/calendar").is_err());
+    }
+
+    #[test]
+    fn gradescope_links() {
+        let config = config();
+        for host in ["www.gradescope.com", "www.gradescope.ca", "gradescope.eu"] {
+            assert_eq!(
+                parse_url(
+                    &config,
+                    &format!(
+                        "https://{}/courses/123/assignments/456/submissions/789",
+                        host
+                    )
+                )
+                .unwrap(),
+                Target::Key {
+                    key: "gradescope:assignment:456".into(),
+                    course_key: Some("gradescope:course:123".into()),
+                    url: Some(format!(
+                        "https://{}/courses/123/assignments/456/submissions/789",
+                        host
+                    )),
+                }
+            );
+        }
+        assert_eq!(
+            key(parse_url(&config, "https://www.gradescope.ca/courses/123").unwrap()),
+            "gradescope:course:123"
+        );
+        assert!(parse_url(&config, "https://www.gradescope.ca/account").is_err());
+    }
+}
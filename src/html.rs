@@ -0,0 +1,402 @@
+use colored::Colorize;
+use scraper::{node::Node, ElementRef, Html};
+
+/// Renders a Canvas HTML body as terminal text, with links listed as footnotes.
+pub fn render(html: &str) -> String {
+    Renderer::new(true).finish(html)
+}
+
+/// Like [`render`], but without colors, for text that ends up in a file.
+pub fn render_plain(html: &str) -> String {
+    Renderer::new(false).finish(html)
+}
+
+struct Renderer {
+    out: String,
+    links: Vec<String>,
+    color: bool,
+    preformatted: bool,
+}
+
+impl Renderer {
+    fn new(color: bool) -> Self {
+        Self {
+            out: String::new(),
+            links: vec![],
+            color,
+            preformatted: false,
+        }
+    }
+
+    fn finish(mut self, html: &str) -> String {
+        let fragment = Html::parse_fragment(html);
+        self.children(fragment.root_element());
+
+        // Blocks each ask for a blank line around them, so nesting can leave more than one.
+        let mut text = String::new();
+        let mut newlines = 0;
+        for c in self.out.trim().chars() {
+            if c == '\n' {
+                newlines += 1;
+                if newlines > 2 {
+                    continue;
+                }
+            } else {
+                newlines = 0;
+            }
+            text.push(c);
+        }
+        let mut text: String = text
+            .lines()
+            .map(|x| x.trim_end())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        if !self.links.is_empty() {
+            text += "\n";
+            for (i, link) in self.links.iter().enumerate() {
+                text += &format!("\n[{}] {}", i + 1, link);
+            }
+        }
+        text
+    }
+
+    fn at_line_start(&self) -> bool {
+        self.out.is_empty() || self.out.ends_with('\n')
+    }
+
+    fn newline(&mut self) {
+        if !self.at_line_start() {
+            self.out.push('\n');
+        }
+    }
+
+    fn blank_line(&mut self) {
+        self.newline();
+        if !self.out.is_empty() && !self.out.ends_with("\n\n") {
+            self.out.push('\n');
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if self.preformatted {
+            self.out += text;
+            return;
+        }
+        let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        if collapsed.is_empty() {
+            if !text.is_empty() && !self.at_line_start() && !self.out.ends_with(' ') {
+                self.out.push(' ');
+            }
+            return;
+        }
+        if text.starts_with(char::is_whitespace)
+            && !self.at_line_start()
+            && !self.out.ends_with(' ')
+        {
+            self.out.push(' ');
+        }
+        self.out += &collapsed;
+        if text.ends_with(char::is_whitespace) {
+            self.out.push(' ');
+        }
+    }
+
+    fn children(&mut self, element: ElementRef) {
+        for child in element.children() {
+            match child.value() {
+                Node::Text(text) => self.text(text),
+                Node::Element(_) => {
+                    if let Some(child) = ElementRef::wrap(child) {
+                        self.element(child);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Renders the children on their own, so the result can be styled or indented.
+    fn capture(&mut self, element: ElementRef) -> String {
+        let outer = std::mem::take(&mut self.out);
+        self.children(element);
+        std::mem::replace(&mut self.out, outer)
+    }
+
+    fn styled(&mut self, element: ElementRef, style: fn(&str) -> String) {
+        let inner = self.capture(element);
+        if self.at_line_start() {
+            self.out += &self.apply(inner.trim_start(), style);
+        } else {
+            self.out += &self.apply(&inner, style);
+        }
+    }
+
+    fn apply(&self, text: &str, style: fn(&str) -> String) -> String {
+        if self.color {
+            style(text)
+        } else {
+            text.to_string()
+        }
+    }
+
+    fn element(&mut self, element: ElementRef) {
+        let name = element.value().name();
+        match name {
+            "script" | "style" | "head" | "title" => {}
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.blank_line();
+                let inner = self.capture(element);
+                let inner = inner.trim();
+                let heading = if self.color {
+                    match name {
+                        "h1" | "h2" => inner.bold().underline().to_string(),
+                        _ => inner.bold().to_string(),
+                    }
+                } else {
+                    match name {
+                        "h1" | "h2" => format!("{}\n{}", inner, "=".repeat(inner.chars().count())),
+                        _ => inner.to_string(),
+                    }
+                };
+                self.out += &heading;
+                self.blank_line();
+            }
+            "p" | "div" | "section" | "article" | "header" | "footer" | "figure" => {
+                self.blank_line();
+                self.children(element);
+                self.blank_line();
+            }
+            "br" => self.out.push('\n'),
+            "hr" => {
+                self.blank_line();
+                self.out += "────────";
+                self.blank_line();
+            }
+            "blockquote" => {
+                self.blank_line();
+                let inner = self.capture(element);
+                self.out += &indent(inner.trim(), "│ ", "│ ");
+                self.blank_line();
+            }
+            "pre" => {
+                self.blank_line();
+                let preformatted = std::mem::replace(&mut self.preformatted, true);
+                let inner = self.capture(element);
+                self.preformatted = preformatted;
+                self.out += &indent(inner.trim_matches('\n'), "    ", "    ");
+                self.blank_line();
+            }
+            "ul" | "ol" => {
+                self.newline();
+                let mut number = element
+                    .value()
+                    .attr("start")
+                    .and_then(|x| x.parse().ok())
+                    .unwrap_or(1);
+                for item in element.child_elements() {
+                    if item.value().name() != "li" {
+                        self.element(item);
+                        continue;
+                    }
+                    let marker = if name == "ol" {
+                        number += 1;
+                        format!("{}. ", number - 1)
+                    } else {
+                        "• ".to_string()
+                    };
+                    let inner = self.capture(item);
+                    let rest = " ".repeat(marker.chars().count());
+                    self.newline();
+                    self.out += &indent(inner.trim(), &marker, &rest);
+                    self.out.push('\n');
+                }
+                self.blank_line();
+            }
+            "li" => {
+                self.newline();
+                self.out += "• ";
+                self.children(element);
+                self.newline();
+            }
+            "table" => self.table(element),
+            "strong" | "b" => self.styled(element, |x| x.bold().to_string()),
+            "em" | "i" => self.styled(element, |x| x.italic().to_string()),
+            "u" => self.styled(element, |x| x.underline().to_string()),
+            "code" => self.styled(element, |x| x.cyan().to_string()),
+            "a" => {
+                let inner = self.capture(element);
+                self.out += &inner;
+                if let Some(href) = element.value().attr("href") {
+                    if !href.starts_with('#') && inner.trim() != href {
+                        self.links.push(href.to_string());
+                        let mark = format!("[{}]", self.links.len());
+                        self.out += &self.apply(&mark, |x| x.blue().to_string());
+                    }
+                }
+            }
+            "img" => {
+                let alt = element
+                    .value()
+                    .attr("alt")
+                    .filter(|x| !x.trim().is_empty())
+                    .or_else(|| element.value().attr("title"));
+                let placeholder = match alt {
+                    Some(alt) => format!("[image: {}]", alt.trim()),
+                    None => "[image]".into(),
+                };
+                self.out += &self.apply(&placeholder, |x| x.bright_black().to_string());
+            }
+            "iframe" | "video" | "audio" => {
+                let placeholder = match element.value().attr("src") {
+                    Some(src) => {
+                        self.links.push(src.to_string());
+                        format!("[{}][{}]", name, self.links.len())
+                    }
+                    None => format!("[{}]", name),
+                };
+                self.out += &self.apply(&placeholder, |x| x.bright_black().to_string());
+            }
+            _ => self.children(element),
+        }
+    }
+
+    fn table(&mut self, table: ElementRef) {
+        let mut rows: Vec<(bool, Vec<String>)> = vec![];
+        for row in table
+            .descendants()
+            .filter_map(ElementRef::wrap)
+            .filter(|x| x.value().name() == "tr")
+        {
+            let cells: Vec<_> = row
+                .child_elements()
+                .filter(|x| matches!(x.value().name(), "td" | "th"))
+                .collect();
+            let header = cells.iter().all(|x| x.value().name() == "th");
+            let cells = cells
+                .into_iter()
+                .map(|cell| {
+                    let inner = self.capture(cell);
+                    inner.split_whitespace().collect::<Vec<_>>().join(" ")
+                })
+                .collect();
+            rows.push((header, cells));
+        }
+
+        let columns = rows.iter().map(|(_, x)| x.len()).max().unwrap_or(0);
+        let widths: Vec<_> = (0..columns)
+            .map(|i| {
+                rows.iter()
+                    .filter_map(|(_, x)| x.get(i))
+                    .map(|x| visible_width(x))
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        self.blank_line();
+        for (header, cells) in rows {
+            let line = cells
+                .iter()
+                .enumerate()
+                .map(|(i, cell)| {
+                    let padding = " ".repeat(widths[i] - visible_width(cell));
+                    format!("{}{}", cell, padding)
+                })
+                .collect::<Vec<_>>()
+                .join("  ");
+            if header {
+                self.out += &self.apply(line.trim_end(), |x| x.bold().to_string());
+            } else {
+                self.out += line.trim_end();
+            }
+            self.out.push('\n');
+        }
+        self.blank_line();
+    }
+}
+
+fn indent(text: &str, first: &str, rest: &str) -> String {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| format!("{}{}", if i == 0 { first } else { rest }, line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Width of text as shown, not counting color codes.
+fn visible_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut escape = false;
+    for c in text.chars() {
+        match c {
+            '\x1b' => escape = true,
+            'm' if escape => escape = false,
+            _ if escape => {}
+            _ => width += 1,
+        }
+    }
+    width
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headings_and_paragraphs() {
+        assert_eq!(
+            render_plain(
+                "<h2>Week   1</h2><p>Read\n  chapter <b>one</b>.</p><h3>Due</h3><p>Friday</p>"
+            ),
+            "Week 1\n======\n\nRead chapter one.\n\nDue\n\nFriday"
+        );
+    }
+
+    #[test]
+    fn nested_lists() {
+        assert_eq!(
+            render_plain(
+                "<ol start=\"3\"><li>Clone the repo</li><li>Run the tests<ul><li>unit</li>\
+                 <li>integration</li></ul></li></ol><p>Then submit.</p>"
+            ),
+            "3. Clone the repo\n4. Run the tests\n   • unit\n   • integration\n\nThen submit."
+        );
+    }
+
+    #[test]
+    fn tables_line_up() {
+        assert_eq!(
+            render_plain(
+                "<table><tr><th>Part</th><th>Points</th></tr><tr><td>Code</td><td>10</td></tr>\
+                 <tr><td>Write-up</td><td>5</td></tr></table>"
+            ),
+            "Part      Points\nCode      10\nWrite-up  5"
+        );
+        assert_eq!(visible_width(&"Points".bold().to_string()), 6);
+        assert_eq!(visible_width("\x1b[1mPoints\x1b[0m"), 6);
+    }
+
+    #[test]
+    fn links_become_footnotes() {
+        assert_eq!(
+            render_plain(
+                "<p>See the <a href=\"https://example.com/spec\">spec</a>, \
+                 <a href=\"https://example.com\">https://example.com</a> and \
+                 <a href=\"#rubric\">the rubric</a>.</p>"
+            ),
+            "See the spec[1], https://example.com and the rubric.\n\n[1] https://example.com/spec"
+        );
+    }
+
+    #[test]
+    fn media_placeholders() {
+        assert_eq!(
+            render_plain(
+                "<p><img src=\"a.png\" alt=\"Circuit diagram\"> <img src=\"b.png\" alt=\"\"></p>\
+                 <iframe src=\"https://example.com/video\"></iframe><video></video>"
+            ),
+            "[image: Circuit diagram] [image]\n\n[iframe][1][video]\n\n[1] https://example.com/video"
+        );
+    }
+}
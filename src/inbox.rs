@@ -0,0 +1,386 @@
+use std::collections::HashMap;
+
+use chrono::Local;
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result, Section,
+};
+use colored::Colorize;
+use reqwest::Method;
+use serde_json::{json, Value};
+
+use crate::{
+    cache,
+    canvas::{find_course, load_courses},
+    canvas_api::{CanvasCourse, Conversation, ConversationMessage, Recipient},
+    canvas_url, colorize, config,
+    editor::{compose, quote},
+    fetch, fetch_paginated, format_datetime, format_duration_full, send_json,
+};
+
+/// Canvas's own names for messaging everyone with a role in a course.
+const ROLES: &[&str] = &["teachers", "tas", "students"];
+
+fn conversations_url(unread: bool) -> String {
+    let mut url = "/api/v1/conversations?per_page=50".to_string();
+    if unread {
+        url += "&scope=unread";
+    }
+    url
+}
+
+fn conversation_url(id: i64) -> String {
+    format!("/api/v1/conversations/{}", id)
+}
+
+/// Read states are part of the cached listings, so they have to be refetched after a change.
+async fn invalidate_listings(config: &config::Config) {
+    cache::invalidate(&canvas_url(config, &conversations_url(false))).await;
+    cache::invalidate(&canvas_url(config, &conversations_url(true))).await;
+}
+
+fn is_unread(conversation: &Conversation) -> bool {
+    conversation.workflow_state == "unread"
+}
+
+fn subject(conversation: &Conversation) -> &str {
+    match conversation.subject.as_deref().map(str::trim) {
+        Some(subject) if !subject.is_empty() => subject,
+        _ => "(no subject)",
+    }
+}
+
+fn participant_names(conversation: &Conversation) -> String {
+    conversation
+        .participants
+        .iter()
+        .map(|x| x.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Shortens a message to its first line, for the listing.
+fn snippet(text: &str, max_chars: usize) -> String {
+    let line = text.lines().find(|x| !x.trim().is_empty()).unwrap_or("");
+    let line = line.trim();
+    if line.chars().count() > max_chars {
+        format!("{}…", line.chars().take(max_chars).collect::<String>())
+    } else {
+        line.to_string()
+    }
+}
+
+pub async fn run_list(config: &config::Config, unread: bool) -> Result<()> {
+    let conversations: Vec<Conversation> =
+        fetch_paginated(config, &conversations_url(unread)).await?;
+
+    if conversations.is_empty() {
+        println!(
+            "{}",
+            if unread {
+                "No unread conversations."
+            } else {
+                "No conversations."
+            }
+        );
+        return Ok(());
+    }
+
+    let mut contexts: Vec<&str> = vec![];
+    let now = Local::now();
+    for conversation in &conversations {
+        let mut heading = format!(
+            "{} {}",
+            format!("{:>8}", conversation.id).bright_black(),
+            subject(conversation).bold()
+        );
+        if let Some(context) = conversation.context_name.as_deref() {
+            let i = contexts
+                .iter()
+                .position(|x| *x == context)
+                .unwrap_or_else(|| {
+                    contexts.push(context);
+                    contexts.len() - 1
+                });
+            heading += &format!(" - {}", colorize(i, context));
+        }
+        if is_unread(conversation) {
+            heading += &" (new)".green().bold().to_string();
+        }
+        println!("{}", heading);
+
+        let mut byline = vec![participant_names(conversation)];
+        if let Some(last_message_at) = conversation.last_message_at {
+            byline.push(format_duration_full(now, last_message_at));
+        }
+        println!("         {}", byline.join(", ").bright_black());
+        if let Some(last_message) = &conversation.last_message {
+            println!("         {}", snippet(last_message, 80));
+        }
+    }
+
+    Ok(())
+}
+
+fn names_by_id(conversation: &Conversation) -> HashMap<i64, &str> {
+    conversation
+        .participants
+        .iter()
+        .map(|x| (x.id, x.name.as_str()))
+        .collect()
+}
+
+fn author_name<'a>(names: &HashMap<i64, &'a str>, message: &ConversationMessage) -> &'a str {
+    names
+        .get(&message.author_id)
+        .copied()
+        .unwrap_or("Unknown user")
+}
+
+/// Canvas marks a conversation as read when it is fetched on its own, so a cached copy would leave
+/// it unread.
+async fn load_conversation(config: &config::Config, id: i64) -> Result<Conversation> {
+    let url = conversation_url(id);
+    cache::invalidate(&canvas_url(config, &url)).await;
+    let conversation: Conversation = fetch(config, &url)
+        .await
+        .wrap_err_with(|| eyre!("Unable to load conversation {}", id))
+        .suggestion("Conversation ids are shown by `canvas inbox`")?;
+    invalidate_listings(config).await;
+    Ok(conversation)
+}
+
+pub async fn run_show(config: &config::Config, id: i64) -> Result<()> {
+    let conversation = load_conversation(config, id).await?;
+    let names = names_by_id(&conversation);
+
+    let mut heading = subject(&conversation).bold().to_string();
+    if let Some(context) = &conversation.context_name {
+        heading += &format!(" - {}", context);
+    }
+    println!("{}", heading);
+    println!("{}", participant_names(&conversation).bright_black());
+
+    let now = Local::now();
+    for message in conversation.messages.iter().rev() {
+        println!();
+        let mut byline = author_name(&names, message).bold().to_string();
+        if let Some(created_at) = message.created_at {
+            byline += &format!(
+                " {}",
+                format!(
+                    "{} ({})",
+                    format_datetime(created_at),
+                    format_duration_full(now, created_at)
+                )
+                .bright_black()
+            );
+        }
+        println!("{}", byline);
+        println!("{}", message.body.trim());
+        for attachment in &message.attachments {
+            let name = attachment
+                .display_name
+                .as_deref()
+                .or(attachment.filename.as_deref())
+                .unwrap_or("attachment");
+            match &attachment.url {
+                Some(url) => println!("  {} {}", format!("[{}]", name).cyan(), url),
+                None => println!("  {}", format!("[{}]", name).cyan()),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The thread, oldest message first, for the context part of the editor template.
+fn thread_context(conversation: &Conversation) -> String {
+    let names = names_by_id(conversation);
+    let mut context = format!(
+        "Replying to {} with {}\n",
+        subject(conversation),
+        participant_names(conversation)
+    );
+    for message in conversation.messages.iter().rev() {
+        context += "\n";
+        context += author_name(&names, message);
+        if let Some(created_at) = message.created_at {
+            context += &format!(", {}", format_datetime(created_at));
+        }
+        context += "\n";
+        context += message.body.trim();
+        context += "\n";
+    }
+    quote(&context)
+}
+
+pub async fn run_reply(config: &config::Config, id: i64) -> Result<()> {
+    let conversation = load_conversation(config, id).await?;
+    let text = compose(&format!("reply-{}", id), "", &thread_context(&conversation)).await?;
+
+    let _: Conversation = send_json(
+        config,
+        Method::POST,
+        &format!("{}/add_message", conversation_url(id)),
+        &json!({ "body": text }),
+    )
+    .await
+    .wrap_err("Unable to send reply")?;
+    invalidate_listings(config).await;
+    cache::invalidate(&canvas_url(config, &conversation_url(id))).await;
+
+    println!(
+        "{}",
+        format!("Replied to {}", subject(&conversation)).bold()
+    );
+
+    Ok(())
+}
+
+fn recipient_id(recipient: &Recipient) -> String {
+    match &recipient.id {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Finds who `query` refers to in a course: a role, a user id, or words from someone's name.
+async fn find_recipient(
+    config: &config::Config,
+    course: &CanvasCourse,
+    query: &str,
+) -> Result<(String, String)> {
+    if let Some(role) = ROLES.iter().find(|x| x.eq_ignore_ascii_case(query)) {
+        return Ok((
+            format!("course_{}_{}", course.id, role),
+            format!("{} of {}", role, course.name),
+        ));
+    }
+
+    let mut url = canvas_url(config, "/api/v1/search/recipients");
+    url.query_pairs_mut()
+        .append_pair("context", &format!("course_{}", course.id))
+        .append_pair("search", query)
+        .append_pair("type", "user")
+        .append_pair("per_page", "50");
+    let recipients: Vec<Recipient> = fetch_paginated(config, url.as_str())
+        .await
+        .wrap_err_with(|| eyre!("Unable to search for `{}` in {}", query, course.name))?;
+
+    let exact: Vec<_> = recipients
+        .iter()
+        .filter(|x| x.name.eq_ignore_ascii_case(query) || recipient_id(x) == query)
+        .collect();
+    let matches: Vec<_> = if exact.is_empty() {
+        recipients.iter().collect()
+    } else {
+        exact
+    };
+
+    match &matches[..] {
+        [recipient] => Ok((recipient_id(recipient), recipient.name.clone())),
+        [] => Err(eyre!("No one in {} matches `{}`", course.name, query))
+            .suggestion("Use teachers, tas or students to message everyone with that role"),
+        _ => Err(eyre!("`{}` matches more than one person", query)).suggestion(format!(
+            "Did you mean one of: {}",
+            matches
+                .iter()
+                .map(|x| x.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+pub async fn run_send(
+    config: &config::Config,
+    course: &str,
+    to: &[String],
+    subject: Option<String>,
+) -> Result<()> {
+    let courses = load_courses(config).await?;
+    let course = find_course(&courses, course)?;
+    let mut recipients = vec![];
+    for query in to {
+        recipients.push(find_recipient(config, course, query).await?);
+    }
+    let names = recipients
+        .iter()
+        .map(|(_, name)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let mut context = format!("Message to {} in {}\n", names, course.name);
+    if subject.is_none() {
+        context += "The first line is the subject, and the rest is the message.\n";
+    }
+    let text = compose("message", "", &quote(&context)).await?;
+    let (subject, body) = match subject {
+        Some(subject) => (subject, text),
+        None => match text.split_once('\n') {
+            Some((subject, body)) if !body.trim().is_empty() => {
+                (subject.trim().to_string(), body.trim().to_string())
+            }
+            _ => {
+                return Err(eyre!("Aborting due to empty message"))
+                    .suggestion("Write the subject on the first line and the message below it")
+            }
+        },
+    };
+
+    // Without `group_conversation`, Canvas starts a separate conversation with each recipient.
+    let _: Vec<Conversation> = send_json(
+        config,
+        Method::POST,
+        "/api/v1/conversations",
+        &json!({
+            "recipients": recipients.iter().map(|(id, _)| id).collect::<Vec<_>>(),
+            "subject": subject,
+            "body": body,
+            "context_code": format!("course_{}", course.id),
+            "group_conversation": true,
+        }),
+    )
+    .await
+    .wrap_err("Unable to send message")?;
+    invalidate_listings(config).await;
+
+    println!("{}", format!("Sent {} to {}", subject, names).bold());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+    use crate::config::test_config;
+
+    #[tokio::test]
+    async fn opening_a_conversation_skips_the_cache() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1/conversations/7"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": 7,
+                "subject": "Office hours",
+                "workflow_state": "read",
+            })))
+            .expect(2)
+            .mount(&server)
+            .await;
+        let mut config = test_config(&server.uri());
+        config.cache_ttl_secs = 3600;
+
+        for _ in 0..2 {
+            let conversation = load_conversation(&config, 7).await.unwrap();
+            assert_eq!(conversation.subject.as_deref(), Some("Office hours"));
+        }
+    }
+}
@@ -1,27 +1,40 @@
+mod announcements;
+mod cache;
+mod canvas;
 mod canvas_api;
 mod config;
+mod discuss;
+mod editor;
+mod grades;
 mod gradescope;
+mod handle;
+mod html;
+mod inbox;
+mod matching;
+mod planner;
 mod progress;
+mod retry;
+mod show;
+mod source;
+mod submit;
 
-use crate::canvas_api::{CanvasAssignment, CanvasCourse};
-use crate::config::Exclusion;
 use chrono::{DateTime, Local};
 use color_eyre::eyre::{ContextCompat, WrapErr};
 use color_eyre::{eyre::eyre, Result, Section};
 use colored::Colorize;
-use config::{config_path, Inclusion};
-use futures::future::try_join_all;
-use gradescope::{
-    load_assignments_for_course, load_courses, GradescopeAssignment, GradescopeCourse,
-};
+use config::config_path;
+use futures::future::{join_all, try_join_all};
 use lazy_static::lazy_static;
 use progress::Progress;
-use reqwest::Url;
-use serde::de::DeserializeOwned;
+use reqwest::{Method, Url};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use source::{enabled_sources, Failure, Item, ItemKind, LoadStatus, Loaded, Status};
 use std::cmp::Reverse;
 use std::{
     cmp::{max, min},
-    collections::HashMap,
+    collections::{BTreeSet, HashMap, HashSet},
+    path::PathBuf,
     str::FromStr,
 };
 use structopt::StructOpt;
@@ -35,33 +48,209 @@ lazy_static! {
     static ref CLIENT: reqwest::Client = reqwest::Client::builder().build().unwrap();
 }
 
-fn decode_json<T: DeserializeOwned>(x: &[u8]) -> Result<T> {
+fn decode_json<T: DeserializeOwned + Serialize>(config: &config::Config, x: &[u8]) -> Result<T> {
     let jd = &mut serde_json::Deserializer::from_slice(x);
 
-    Ok(serde_path_to_error::deserialize(jd)?)
+    let decoded = serde_path_to_error::deserialize(jd)?;
+
+    if config.strict {
+        check_schema(x, &decoded)?;
+    }
+
+    Ok(decoded)
 }
 
-async fn fetch<T: DeserializeOwned>(config: &config::Config, url: &str) -> Result<T> {
-    decode_json(
-        &CLIENT
-            .get(
-                Url::from_str(&config.canvas_url)
-                    .unwrap()
-                    .join(url)
-                    .unwrap(),
-            )
+/// Fails if any field the models know about was absent from the response.
+fn check_schema<T: Serialize>(x: &[u8], decoded: &T) -> Result<()> {
+    fn find_missing(raw: &Value, decoded: &Value, path: &str, missing: &mut BTreeSet<String>) {
+        match (raw, decoded) {
+            (Value::Object(raw), Value::Object(decoded)) => {
+                for (key, value) in decoded {
+                    let path = format!("{}.{}", path, key);
+                    match raw.get(key) {
+                        Some(raw) => find_missing(raw, value, &path, missing),
+                        None => {
+                            missing.insert(path);
+                        }
+                    }
+                }
+            }
+            (Value::Array(raw), Value::Array(decoded)) => {
+                for (raw, decoded) in raw.iter().zip(decoded) {
+                    find_missing(raw, decoded, &format!("{}[]", path), missing);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let raw: Value = serde_json::from_slice(x)?;
+    let mut missing = BTreeSet::new();
+    find_missing(&raw, &serde_json::to_value(decoded)?, "", &mut missing);
+
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(eyre!(
+            "Response is missing fields: {}",
+            missing.into_iter().collect::<Vec<_>>().join(", ")
+        ))
+    }
+}
+
+fn canvas_url(config: &config::Config, url: &str) -> Url {
+    Url::from_str(&config.canvas_url)
+        .unwrap()
+        .join(url)
+        .unwrap()
+}
+
+async fn fetch_raw(config: &config::Config, url: &str) -> Result<cache::Entry> {
+    fetch_entry(config, url, false).await
+}
+
+/// Fetches through the cache, or past it when `fresh`, for reads that a change depends on.
+async fn fetch_entry(config: &config::Config, url: &str, fresh: bool) -> Result<cache::Entry> {
+    let url = canvas_url(config, url);
+    let request = || {
+        CLIENT
+            .get(url.clone())
             .header("Authorization", format!("Bearer {}", config.token))
-            .send()
-            .await
-            .wrap_err_with(|| eyre!("Unable to fetch {}", url))?
-            .error_for_status()
-            .wrap_err("Server returned error")
-            .suggestion("Make sure your credentials are valid")?
-            .bytes()
-            .await
-            .wrap_err("Failed to read data from server")?,
-    )
-    .wrap_err_with(|| eyre!("Unable to parse {}", url))
+    };
+    let entry = if fresh {
+        cache::get_fresh(config, &url, request).await
+    } else {
+        cache::get(config, &url, request).await
+    };
+    entry
+        .wrap_err_with(|| eyre!("Unable to fetch {}", url))
+        .suggestion("Make sure your credentials are valid")
+}
+
+/// Sends a request that changes something on Canvas. These are neither cached nor retried.
+async fn send_json<T: DeserializeOwned + Serialize>(
+    config: &config::Config,
+    method: Method,
+    url: &str,
+    body: &impl Serialize,
+) -> Result<T> {
+    let body = CLIENT
+        .request(method, canvas_url(config, url))
+        .header("Authorization", format!("Bearer {}", config.token))
+        .json(body)
+        .send()
+        .await
+        .wrap_err_with(|| eyre!("Unable to send {}", url))?
+        .error_for_status()
+        .wrap_err("Server returned error")
+        .suggestion("Make sure your credentials are valid")?
+        .bytes()
+        .await
+        .wrap_err("Failed to read data from server")?;
+    decode_json(config, &body).wrap_err_with(|| eyre!("Unable to parse {}", url))
+}
+
+/// Sends a request that changes something on Canvas and has no response body.
+async fn send_empty(config: &config::Config, method: Method, url: &str) -> Result<()> {
+    CLIENT
+        .request(method, canvas_url(config, url))
+        .header("Authorization", format!("Bearer {}", config.token))
+        .header("Content-Length", "0")
+        .send()
+        .await
+        .wrap_err_with(|| eyre!("Unable to send {}", url))?
+        .error_for_status()
+        .wrap_err("Server returned error")
+        .suggestion("Make sure your credentials are valid")?;
+    Ok(())
+}
+
+async fn fetch<T: DeserializeOwned + Serialize>(config: &config::Config, url: &str) -> Result<T> {
+    let entry = fetch_raw(config, url).await?;
+    decode_json(config, entry.body.as_bytes()).wrap_err_with(|| eyre!("Unable to parse {}", url))
+}
+
+/// Like [`fetch`], but never served from the cache without asking the server first.
+async fn fetch_fresh<T: DeserializeOwned + Serialize>(
+    config: &config::Config,
+    url: &str,
+) -> Result<T> {
+    let entry = fetch_entry(config, url, true).await?;
+    decode_json(config, entry.body.as_bytes()).wrap_err_with(|| eyre!("Unable to parse {}", url))
+}
+
+/// Fetches every page of a paginated Canvas endpoint by following the `Link` header.
+async fn fetch_paginated<T: DeserializeOwned + Serialize>(
+    config: &config::Config,
+    url: &str,
+) -> Result<Vec<T>> {
+    let entry = fetch_raw(config, url).await?;
+    let mut items: Vec<T> = decode_json(config, entry.body.as_bytes())
+        .wrap_err_with(|| eyre!("Unable to parse {}", url))?;
+    let mut links = parse_link_header(entry.link.as_deref());
+
+    // Numbered pages can be requested all at once; bookmark-style pages have to be walked.
+    if let (Some(next), Some(last)) = (links.get("next"), links.get("last")) {
+        if let (Some(first_page), Some(last_page)) = (page_number(next), page_number(last)) {
+            let pages = try_join_all((first_page..=last_page).map(|page| {
+                let url = with_page(next, page);
+                async move { fetch::<Vec<T>>(config, url.as_str()).await }
+            }))
+            .await?;
+            items.extend(pages.into_iter().flatten());
+            return Ok(items);
+        }
+    }
+
+    while let Some(next) = links.remove("next") {
+        let entry = fetch_raw(config, next.as_str()).await?;
+        items.extend(
+            decode_json::<Vec<T>>(config, entry.body.as_bytes())
+                .wrap_err_with(|| eyre!("Unable to parse {}", next))?,
+        );
+        links = parse_link_header(entry.link.as_deref());
+    }
+
+    Ok(items)
+}
+
+fn parse_link_header(header: Option<&str>) -> HashMap<String, Url> {
+    header
+        .into_iter()
+        .flat_map(|x| x.split(','))
+        .filter_map(|link| {
+            let mut parts = link.split(';');
+            let url = parts.next()?.trim().strip_prefix('<')?.strip_suffix('>')?;
+            let rel = parts.find_map(|x| {
+                x.trim()
+                    .strip_prefix("rel=")
+                    .map(|x| x.trim_matches('"').to_string())
+            })?;
+            Some((rel, Url::parse(url).ok()?))
+        })
+        .collect()
+}
+
+fn page_number(url: &Url) -> Option<u64> {
+    url.query_pairs()
+        .find(|(k, _)| k == "page")
+        .and_then(|(_, v)| v.parse().ok())
+}
+
+fn with_page(url: &Url, page: u64) -> Url {
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(k, v)| {
+            if k == "page" {
+                (k.into_owned(), page.to_string())
+            } else {
+                (k.into_owned(), v.into_owned())
+            }
+        })
+        .collect();
+    let mut url = url.clone();
+    url.query_pairs_mut().clear().extend_pairs(pairs);
+    url
 }
 
 fn format_time(time: DateTime<Local>) -> String {
@@ -105,90 +294,218 @@ fn format_duration_full(a: DateTime<Local>, b: DateTime<Local>) -> String {
     }
 }
 
+const ITEM_HELP: &str =
+    "Number or short hash from the last `todo`, an id, a link, or part of the name";
+
+#[derive(StructOpt, Clone, Debug)]
+struct Args {
+    #[structopt(
+        long,
+        global = true,
+        help = "Fail when Canvas omits fields instead of tolerating it"
+    )]
+    strict: bool,
+    #[structopt(subcommand)]
+    command: Opt,
+}
+
 #[derive(StructOpt, Clone, Debug)]
 enum Opt {
     #[structopt(about = "Displays a list of upcoming assignments")]
     Todo {
         #[structopt(long)]
         show_all: bool,
+        #[structopt(
+            long = "type",
+            help = "Only show these kinds of items: assignment, quiz, discussion, page, event, note"
+        )]
+        kinds: Vec<ItemKind>,
+        #[structopt(long, help = "Only use cached data")]
+        offline: bool,
+    },
+    #[structopt(about = "Adds an assignment or course to the exclusion list")]
+    Exclude {
+        #[structopt(help = ITEM_HELP)]
+        item: String,
+    },
+    #[structopt(about = "Opens an item in the browser")]
+    Open {
+        #[structopt(help = ITEM_HELP)]
+        item: String,
+    },
+    #[structopt(about = "Marks an item as done in the Canvas planner")]
+    Done {
+        #[structopt(help = ITEM_HELP)]
+        item: String,
+        #[structopt(long = "type", help = "Type of the item, when given as a raw id")]
+        kind: Option<ItemKind>,
+    },
+    #[structopt(about = "Marks an item as not done in the Canvas planner")]
+    Undone {
+        #[structopt(help = ITEM_HELP)]
+        item: String,
+        #[structopt(long = "type", help = "Type of the item, when given as a raw id")]
+        kind: Option<ItemKind>,
+    },
+    #[structopt(about = "Displays current scores, or a course's scores by assignment group")]
+    Grades { course: Option<String> },
+    #[structopt(about = "Calculates a course grade with hypothetical scores")]
+    Whatif {
+        course: String,
+        #[structopt(
+            long = "score",
+            parse(try_from_str = grades::parse_hypothetical),
+            help = "Hypothetical score, as <assignment>=<points>"
+        )]
+        scores: Vec<(String, f64)>,
+        #[structopt(long, help = "Grade to reach, in percent")]
+        target: Option<f64>,
+        #[structopt(long, help = "Assignment to solve for to reach the target")]
+        solve: Option<String>,
+    },
+    #[structopt(about = "Displays an assignment's details and description")]
+    Show {
+        #[structopt(help = ITEM_HELP)]
+        assignment: String,
+    },
+    #[structopt(
+        about = "Submits files, text or a URL to an assignment",
+        long_about = "Submits files, text or a URL to an assignment. Without files, --text or --url, opens $EDITOR to write a text entry in Markdown."
+    )]
+    Submit {
+        #[structopt(help = ITEM_HELP)]
+        assignment: String,
+        #[structopt(parse(from_os_str), conflicts_with_all = &["text", "url"])]
+        files: Vec<PathBuf>,
+        #[structopt(
+            long,
+            parse(from_os_str),
+            conflicts_with = "url",
+            help = "Markdown file to submit as a text entry, or - for stdin"
+        )]
+        text: Option<PathBuf>,
+        #[structopt(long, help = "Link to submit as a website URL")]
+        url: Option<String>,
+    },
+    #[structopt(about = "Displays recent announcements and marks them as read")]
+    Announcements {
+        #[structopt(long, help = "Only show announcements from this course")]
+        course: Option<String>,
+        #[structopt(
+            long,
+            parse(try_from_str = announcements::parse_since),
+            help = "Show announcements since a date (2024-09-01) or a number of days ago (7d)"
+        )]
+        since: Option<DateTime<Local>>,
+        #[structopt(long, help = "Only show unread announcements")]
+        unread: bool,
+        #[structopt(long, help = "Do not mark the announcements shown as read")]
+        keep_unread: bool,
+    },
+    #[structopt(about = "Displays a discussion and its replies, or posts to it")]
+    Discuss {
+        #[structopt(help = ITEM_HELP)]
+        assignment: Option<String>,
+        #[structopt(subcommand)]
+        command: Option<DiscussCommand>,
+    },
+    #[structopt(about = "Lists Canvas Inbox conversations, or reads and sends messages")]
+    Inbox {
+        #[structopt(long, help = "Only list unread conversations")]
+        unread: bool,
+        #[structopt(subcommand)]
+        command: Option<InboxCommand>,
     },
-    #[structopt(about = "Adds an assignment to the exclusion list")]
-    Exclude { assignment_id: i64 },
+    #[structopt(about = "Gradescope commands")]
+    Gradescope(GradescopeCommand),
     #[structopt(about = "Displays the due date for the next assignment")]
-    NextDue,
+    NextDue {
+        #[structopt(long, help = "Only use cached data")]
+        offline: bool,
+    },
 }
 
-fn should_show(config: &config::Config, assignment: &Assignment) -> bool {
-    if let Some(id) = assignment.assignment_id() {
-        if config
-            .include
-            .contains(&Inclusion::ByAssignmentId { assignment_id: id })
-        {
-            return true;
-        }
+#[derive(StructOpt, Clone, Debug)]
+enum DiscussCommand {
+    #[structopt(about = "Posts to a discussion, or replies to an entry, written in $EDITOR")]
+    Reply {
+        #[structopt(help = ITEM_HELP)]
+        assignment: String,
+        #[structopt(long, help = "Entry to reply to, as numbered by `canvas discuss`")]
+        to: Option<i64>,
+    },
+}
+
+#[derive(StructOpt, Clone, Debug)]
+enum InboxCommand {
+    #[structopt(about = "Displays a conversation and marks it as read")]
+    Show { id: i64 },
+    #[structopt(about = "Replies to a conversation, written in $EDITOR")]
+    Reply { id: i64 },
+    #[structopt(about = "Starts a conversation, written in $EDITOR")]
+    Send {
+        #[structopt(long)]
+        course: String,
+        #[structopt(
+            long,
+            required = true,
+            help = "Who to message: teachers, tas, students, or a person's name or id"
+        )]
+        to: Vec<String>,
+        #[structopt(long, help = "Subject, instead of the first line of the message")]
+        subject: Option<String>,
+    },
+}
+
+#[derive(StructOpt, Clone, Debug)]
+enum GradescopeCommand {
+    #[structopt(about = "Logs in to Gradescope and saves the session")]
+    Login {
+        #[structopt(long)]
+        email: Option<String>,
+    },
+    #[structopt(about = "Lists Gradescope courses by term, and which ones are loaded")]
+    Courses,
+    #[structopt(about = "Downloads the graded copy or the submitted files of an assignment")]
+    Download {
+        #[structopt(help = ITEM_HELP, required_unless = "course")]
+        assignment: Option<String>,
+        #[structopt(
+            long,
+            conflicts_with = "assignment",
+            help = "Download every assignment in a course"
+        )]
+        course: Option<String>,
+        #[structopt(long, default_value = ".", parse(from_os_str))]
+        dir: PathBuf,
+    },
+    #[structopt(about = "Displays the autograder results of a submission")]
+    Results {
+        #[structopt(help = ITEM_HELP)]
+        assignment: String,
+    },
+}
+
+fn should_show(config: &config::Config, item: &Item) -> bool {
+    if config.include.iter().any(|x| x.matches(item)) {
+        return true;
     }
 
-    if let Some(due) = assignment.due_at() {
+    if let Some(due) = item.due_at {
         if let Some(overdue_offset) = config.hide_overdue_after_days {
             if (Local::now() - due).num_days() > overdue_offset {
                 return false;
             }
         }
-        match assignment {
-            Assignment::Canvas(_, assignment) => {
-                if config.hide_overdue_without_submission {
-                    let (_, submission) = process_submission(assignment, 0.0);
-                    if !submission && (Local::now() > due) {
-                        return false;
-                    }
-                }
-            }
-            Assignment::Gradescope(_, assignment) => {
-                if assignment.submitted {
-                    return false;
-                }
-            }
-        }
-    }
-
-    if let Assignment::Canvas(_, assignment) = assignment {
-        if let Some(submission) = &assignment.submission {
-            if !(submission.submitted_at.is_none()
-                || assignment.peer_reviews && submission.discussion_entries.len() < 2)
-            {
-                return false;
-            }
+        if config.hide_overdue_without_submission
+            && !item.has_online_submission()
+            && Local::now() > due
+        {
+            return false;
         }
     }
 
-    true
-}
-
-fn process_submission(assignment: &CanvasAssignment, points: f64) -> (String, bool) {
-    let mut online_submission = false;
-    let types = assignment
-        .submission_types
-        .iter()
-        .map(|x| match x.as_str() {
-            "none" => "No submission".to_string(),
-            "on_paper" => "On paper".to_string(),
-            x => {
-                let text = match x {
-                    "online_text_entry" => "Text entry",
-                    "online_upload" => "File upload",
-                    "online_quiz" => "Quiz",
-                    "discussion_topic" => "Discussion",
-                    "media_recording" => "Media recording",
-                    "external_tool" => "External tool",
-                    _ => "Unknown",
-                };
-                online_submission = true;
-                text.purple().to_string()
-            }
-        });
-    let types: Vec<_> = types.collect();
-    let types = types.join(", ");
-    (format!("{} - {} points", types, points), online_submission)
+    !matches!(item.status, Status::Submitted | Status::Done)
 }
 
 fn colorize(i: usize, s: &str) -> String {
@@ -203,26 +520,158 @@ fn colorize(i: usize, s: &str) -> String {
 #[tokio::main]
 async fn main() -> Result<()> {
     color_eyre::install()?;
-    let opt = Opt::from_args();
-    let config = &config::read_config().wrap_err("Unable to read configuration file")?;
+    let args = Args::from_args();
+    let opt = args.command;
+    let mut config = config::read_config().wrap_err("Unable to read configuration file")?;
+    config.strict = args.strict;
+    // A session saved by `gradescope login` is newer than a cookie pasted into the config, which
+    // may well be the expired one the user logged in to replace.
+    if let Some(session) = gradescope::load_session() {
+        config.gradescope_cookie = Some(session);
+    }
+    config.offline = matches!(
+        opt,
+        Opt::Todo { offline: true, .. } | Opt::NextDue { offline: true }
+    );
+    let config = &config;
 
     match opt {
-        Opt::Todo { show_all } => {
-            run_todo(config, show_all).await?;
+        Opt::Todo {
+            show_all, kinds, ..
+        } => {
+            let status = run_todo(config, show_all, &kinds).await?;
+            status.exit();
+        }
+        Opt::Exclude { item } => {
+            run_exclude(config, &item).await?;
+        }
+        Opt::Open { item } => {
+            run_open(config, &item).await?;
+        }
+        Opt::Grades { course } => {
+            grades::run_grades(config, course).await?;
+        }
+        Opt::Whatif {
+            course,
+            scores,
+            target,
+            solve,
+        } => {
+            grades::run_whatif(config, &course, &scores, target, solve).await?;
+        }
+        Opt::Show { assignment } => {
+            show::run_show(config, &assignment).await?;
+        }
+        Opt::Submit {
+            assignment,
+            files,
+            text,
+            url,
+        } => {
+            let content = match (text, url) {
+                (Some(text), _) => submit::Content::Text(text),
+                (_, Some(url)) => submit::Content::Url(url),
+                _ if !files.is_empty() => submit::Content::Files(files),
+                _ => submit::Content::Editor,
+            };
+            submit::run_submit(config, &assignment, content).await?;
+        }
+        Opt::Done { item, kind } => {
+            let plannables = planner::resolve_plannable(config, &item, kind).await?;
+            planner::set_done(config, &plannables, true).await?;
+            let (kind, id) = plannables[0];
+            println!("Marked {} {} as done.", kind.label().to_lowercase(), id);
+        }
+        Opt::Undone { item, kind } => {
+            let plannables = planner::resolve_plannable(config, &item, kind).await?;
+            planner::set_done(config, &plannables, false).await?;
+            let (kind, id) = plannables[0];
+            println!("Marked {} {} as not done.", kind.label().to_lowercase(), id);
+        }
+        Opt::Announcements {
+            course,
+            since,
+            unread,
+            keep_unread,
+        } => {
+            announcements::run_announcements(config, course, since, unread, keep_unread).await?;
+        }
+        Opt::Discuss {
+            command: Some(DiscussCommand::Reply { assignment, to }),
+            ..
+        } => {
+            discuss::run_reply(config, &assignment, to).await?;
+        }
+        Opt::Discuss {
+            assignment: Some(assignment),
+            command: None,
+        } => {
+            discuss::run_discuss(config, &assignment).await?;
+        }
+        Opt::Discuss {
+            assignment: None,
+            command: None,
+        } => {
+            return Err(eyre!("No discussion given"))
+                .suggestion("Pass the discussion to show, as in `canvas discuss 3`");
+        }
+        Opt::Inbox {
+            unread,
+            command: None,
+        } => {
+            inbox::run_list(config, unread).await?;
+        }
+        Opt::Inbox {
+            command: Some(InboxCommand::Show { id }),
+            ..
+        } => {
+            inbox::run_show(config, id).await?;
         }
-        Opt::Exclude { assignment_id } => {
-            run_exclude(assignment_id).await?;
+        Opt::Inbox {
+            command: Some(InboxCommand::Reply { id }),
+            ..
+        } => {
+            inbox::run_reply(config, id).await?;
         }
-        Opt::NextDue => {
-            let mut all_assignments = load_all_assignments(config).await?;
+        Opt::Inbox {
+            command:
+                Some(InboxCommand::Send {
+                    course,
+                    to,
+                    subject,
+                }),
+            ..
+        } => {
+            inbox::run_send(config, &course, &to, subject).await?;
+        }
+        Opt::Gradescope(GradescopeCommand::Login { email }) => {
+            gradescope::run_login(config, email).await?;
+        }
+        Opt::Gradescope(GradescopeCommand::Download {
+            assignment,
+            course,
+            dir,
+        }) => {
+            gradescope::run_download(config, assignment, course, &dir).await?;
+        }
+        Opt::Gradescope(GradescopeCommand::Courses) => {
+            gradescope::run_courses(config).await?;
+        }
+        Opt::Gradescope(GradescopeCommand::Results { assignment }) => {
+            gradescope::run_results(config, &assignment).await?;
+        }
+        Opt::NextDue { .. } => {
+            let loaded = load_all_assignments(config).await?;
+            let status = loaded.report_failures()?;
+            let mut all_assignments = loaded.items;
 
-            all_assignments.retain(|x| x.due_at().is_some());
-            all_assignments.sort_by_key(|x| x.due_at());
+            all_assignments.retain(|x| x.due_at.is_some());
+            all_assignments.sort_by_key(|x| x.due_at);
 
             let now = Local::now();
             for assignment in all_assignments {
                 if should_show(config, &assignment) {
-                    if let Some(due) = assignment.due_at() {
+                    if let Some(due) = assignment.due_at {
                         if due > now {
                             println!("{}", format_duration(now, due));
                             break;
@@ -230,13 +679,35 @@ async fn main() -> Result<()> {
                     }
                 }
             }
+            status.exit();
         }
     }
 
     Ok(())
 }
 
-async fn run_exclude(assignment_id: i64) -> Result<()> {
+async fn run_exclude(config: &config::Config, query: &str) -> Result<()> {
+    let (field, id) = match handle::resolve(config, query).await? {
+        handle::Target::Key { key, .. } => {
+            if let Some(id) = handle::canvas_id(&key, "assignment") {
+                ("assignment_id", id)
+            } else if let Some(id) = handle::canvas_id(&key, "course") {
+                ("class_id", id)
+            } else if let Some(id) = handle::gradescope_id(&key, "assignment") {
+                ("gradescope_assignment_id", id)
+            } else if let Some(id) = handle::gradescope_id(&key, "course") {
+                ("gradescope_course_id", id)
+            } else {
+                return Err(eyre!("Only assignments and courses can be excluded"));
+            }
+        }
+        handle::Target::Id(id) => ("assignment_id", id),
+        handle::Target::Name(_) => {
+            return Err(eyre!("No item in the last listing matches `{}`", query))
+                .suggestion("Run `canvas todo` first, or pass an assignment id or link")
+        }
+    };
+
     let old_config = read_to_string(config_path()).await?;
     let mut doc: Document = old_config.parse()?;
 
@@ -246,7 +717,7 @@ async fn run_exclude(assignment_id: i64) -> Result<()> {
         .wrap_err("`exclude` is not an array of tables")?
         .append({
             let mut t = Table::new();
-            t["assignment_id"] = value(assignment_id);
+            t[field] = value(id);
             t
         });
 
@@ -255,148 +726,126 @@ async fn run_exclude(assignment_id: i64) -> Result<()> {
         .write_all(doc.to_string().as_bytes())
         .await?;
 
-    println!("Assignment {} excluded successfully.", assignment_id);
+    if field.ends_with("assignment_id") {
+        println!("Assignment {} excluded successfully.", id);
+    } else {
+        println!("Course {} excluded successfully.", id);
+    }
 
     Ok(())
 }
 
-#[derive(Debug)]
-enum Assignment {
-    Canvas(CanvasCourse, CanvasAssignment),
-    Gradescope(GradescopeCourse, GradescopeAssignment),
-}
+async fn run_open(config: &config::Config, query: &str) -> Result<()> {
+    let url = match handle::resolve(config, query).await? {
+        handle::Target::Key { url: Some(url), .. } => url,
+        _ => canvas::lookup_assignment(config, query).await?.1.html_url,
+    };
 
-impl Assignment {
-    fn assignment_id(&self) -> Option<i64> {
-        match self {
-            Assignment::Canvas(_, a) => Some(a.id),
-            Assignment::Gradescope(_, _) => None,
-        }
-    }
+    let mut command = if cfg!(target_os = "macos") {
+        std::process::Command::new("open")
+    } else if cfg!(windows) {
+        let mut command = std::process::Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else {
+        std::process::Command::new("xdg-open")
+    };
+    command
+        .arg(&url)
+        .spawn()
+        .wrap_err("Unable to open a browser")
+        .suggestion(format!("Open {} manually", url))?;
 
-    fn due_at(&self) -> Option<DateTime<Local>> {
-        match self {
-            Assignment::Canvas(_, a) => a.due_at,
-            Assignment::Gradescope(_, a) => a.due_at,
-        }
-    }
+    Ok(())
 }
 
-async fn run_todo(config: &config::Config, show_all: bool) -> Result<()> {
-    let all_assignments = load_all_assignments(config).await?;
+async fn run_todo(
+    config: &config::Config,
+    show_all: bool,
+    kinds: &[ItemKind],
+) -> Result<LoadStatus> {
+    let mut loaded = load_all_assignments(config).await?;
+    let all_assignments = std::mem::take(&mut loaded.items);
 
     let now = Local::now();
 
     let mut next_assignment_due_at = None;
     let mut next_submission_due_at = None;
     let mut locked_count = 0;
+    let mut listing = vec![];
 
     let mut color_id = 0;
-    let mut courses_color: HashMap<i64, String> = HashMap::new();
-    let mut get_course_color = |id: i64, name: &str| {
-        if let Some(s) = courses_color.get(&id) {
+    let mut courses_color: HashMap<String, String> = HashMap::new();
+    let mut get_course_color = |key: &str, name: &str| {
+        if let Some(s) = courses_color.get(key) {
             return s.to_string();
         }
         let s = colorize(color_id, name);
-        courses_color.insert(id, s.to_string());
+        courses_color.insert(key.to_string(), s.to_string());
         color_id += 1;
         s
     };
 
-    for assignment in all_assignments {
-        if let Some(due) = assignment.due_at() {
-            if show_all || should_show(config, &assignment) {
-                match assignment {
-                    Assignment::Canvas(course, assignment) => {
-                        if let Some(points) = assignment.points_possible {
-                            if let Some(submission) = &assignment.submission {
-                                if config.hide_locked && assignment.locked_for_user {
-                                    locked_count += 1;
-                                } else {
-                                    println!(
-                                        "{}",
-                                        format!(
-                                            "Due {} ({}) - {}{}",
-                                            if due < now {
-                                                format_datetime(due).red().bold()
-                                            } else {
-                                                format_datetime(due).bold()
-                                            },
-                                            format_duration_full(now, due),
-                                            get_course_color(course.id, &course.name),
-                                            if submission.submitted_at.is_some() {
-                                                " (completed)".white()
-                                            } else {
-                                                "".white()
-                                            }
-                                        )
-                                        .underline()
-                                    );
-                                    let (submission_text, online_submission) =
-                                        process_submission(&assignment, points);
-                                    println!(
-                                        "  {} {}",
-                                        assignment.name.trim(),
-                                        format!("({})", submission_text).bright_black()
-                                    );
-                                    println!("  {}", assignment.html_url);
-                                    println!();
-                                    if due > now && submission.submitted_at.is_none() {
-                                        if let Some(due_at) = assignment.due_at {
-                                            next_assignment_due_at = Some(due_at);
-                                            if online_submission {
-                                                next_submission_due_at = Some(due_at);
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    Assignment::Gradescope(course, assignment) => {
-                        println!(
-                            "{}",
-                            format!(
-                                "Due {} ({}) - {}{}",
-                                if due < now {
-                                    format_datetime(due).red().bold()
-                                } else {
-                                    format_datetime(due).bold()
-                                },
-                                format_duration_full(now, due),
-                                get_course_color(course.id, &course.name),
-                                if assignment.submitted {
-                                    " (completed)".white()
-                                } else {
-                                    "".white()
-                                }
-                            )
-                            .underline()
-                        );
-                        println!(
-                            "  {} {}",
-                            assignment.name.trim(),
-                            format!("({})", "Gradescope".purple()).bright_black()
-                        );
-                        if let Some(link) = assignment.link {
-                            println!("  https://www.gradescope.com{}", link);
+    for item in all_assignments {
+        if !kinds.is_empty() && !kinds.contains(&item.kind) {
+            continue;
+        }
+        if let Some(due) = item.due_at {
+            if show_all || should_show(config, &item) {
+                if config.hide_locked && item.locked {
+                    locked_count += 1;
+                    continue;
+                }
+                println!(
+                    "{}",
+                    format!(
+                        "Due {} ({}) - {}{}",
+                        if due < now {
+                            format_datetime(due).red().bold()
                         } else {
-                            println!("  https://www.gradescope.com/courses/{}", course.id)
-                        }
-                        println!();
-
-                        if let Some(due_at) = assignment.due_at {
-                            if due_at > now {
-                                next_assignment_due_at = Some(due_at);
-                                next_submission_due_at = Some(due_at);
-                            }
+                            format_datetime(due).bold()
+                        },
+                        format_duration_full(now, due),
+                        get_course_color(&item.course.key, &item.course.name),
+                        match item.status {
+                            Status::Pending => "".white(),
+                            Status::Done => " (done)".white(),
+                            _ => " (completed)".white(),
                         }
+                    )
+                    .underline()
+                );
+                listing.push(handle::Listed::from(&item));
+                if let Some(late_due_at) = item.late_due_at.filter(|x| due < now && *x > now) {
+                    println!(
+                        "  {}",
+                        format!("Late window until {}", format_datetime(late_due_at)).yellow()
+                    );
+                }
+                println!(
+                    "  {} {} {}",
+                    handle::format_handle(listing.len(), &item.key).bright_black(),
+                    item.title,
+                    format!("({})", item.details()).bright_black()
+                );
+                println!("  {}", item.url);
+                for url in &item.other_urls {
+                    println!("  {}", url);
+                }
+                println!();
+                if due > now && item.status == Status::Pending {
+                    next_assignment_due_at = Some(due);
+                    if item.has_online_submission() {
+                        next_submission_due_at = Some(due);
                     }
                 }
             }
         }
     }
 
+    // Handles are a convenience, so a listing that cannot be saved should not fail the command.
+    let _ = handle::save_listing(&listing).await;
+
     if locked_count != 0 {
         println!(
             "{}",
@@ -410,6 +859,23 @@ async fn run_todo(config: &config::Config, show_all: bool) -> Result<()> {
         println!();
     }
 
+    // The summary is extra, so it is left out rather than failing the listing.
+    if config.sources.iter().any(|x| x == "canvas") {
+        if let Ok(unread) = announcements::count_unread(config).await {
+            if unread > 0 {
+                println!(
+                    "{}",
+                    format!(
+                        "{} unread announcement{} (see `canvas announcements`)",
+                        unread,
+                        if unread == 1 { "" } else { "s" }
+                    )
+                    .yellow()
+                );
+            }
+        }
+    }
+
     if let Some(next_assignment_due_at) = next_assignment_due_at {
         println!(
             "Next assignment is due in {}",
@@ -424,107 +890,161 @@ async fn run_todo(config: &config::Config, show_all: bool) -> Result<()> {
         )
     };
 
-    Ok(())
+    if config.offline {
+        if let Some(as_of) = cache::served_as_of() {
+            println!(
+                "{}",
+                format!(
+                    "Data as of {} ({})",
+                    format_datetime(as_of),
+                    format_duration_full(now, as_of)
+                )
+                .bright_black()
+            );
+        }
+    }
+
+    loaded.report_failures()
 }
 
-async fn load_all_assignments(config: &config::Config) -> Result<Vec<Assignment>> {
+async fn load_all_assignments(config: &config::Config) -> Result<Loaded> {
+    let sources = enabled_sources(config)?;
     let progress = Progress::new();
 
-    let (canvas_assignments, gradescope_assignments) = tokio::try_join!(
-        load_canvas(&progress, config),
-        load_gradescope(&progress, config),
-    )?;
-
-    let mut all_assignments: Vec<_> = gradescope_assignments
-        .into_iter()
-        .flat_map(|(c, a)| a.into_iter().map(move |x| (c.clone(), x)))
-        .map(|(c, a)| Assignment::Gradescope(c, a))
-        .chain(
-            canvas_assignments
-                .into_iter()
-                .flat_map(|(c, a)| a.into_iter().map(move |x| (c.clone(), x)))
-                .map(|(c, a)| Assignment::Canvas(c, a)),
-        )
-        .collect();
+    let results = join_all(sources.iter().map(|x| x.load(&progress, config))).await;
 
     progress.finish();
 
-    all_assignments.retain(|a| {
-        !config.exclude.iter().any(|x| match a.assignment_id() {
-            Some(id) => x == &Exclusion::ByAssignmentId { assignment_id: id },
-            None => false,
-        })
-    });
+    let mut loaded = Loaded::default();
+    for (source, result) in sources.iter().zip(results) {
+        match result {
+            Ok(x) => loaded.extend(x),
+            Err(error) => loaded.failures.push(Failure {
+                source: source.name().into(),
+                error,
+            }),
+        }
+    }
 
-    all_assignments.sort_by_key(|x| Reverse(x.due_at()));
+    // Sources are listed in order of preference, so the first copy of an item wins.
+    let mut seen = HashSet::new();
+    loaded.items.retain(|x| seen.insert(x.key.clone()));
 
-    Ok(all_assignments)
-}
+    // Merged items remember both keys, so excluding either copy hides them.
+    loaded.items = matching::merge_duplicates(loaded.items, &config.matching);
 
-async fn load_canvas(
-    progress: &Progress,
-    config: &config::Config,
-) -> Result<Vec<(CanvasCourse, Vec<CanvasAssignment>)>> {
-    let mut canvas_courses: Vec<CanvasCourse> = progress
-        .wrap(
-            "Loading course list",
-            fetch(
-                config,
-                "/api/v1/courses?enrollment_state=active&per_page=10000",
-            ),
-        )
-        .await?;
+    loaded
+        .items
+        .retain(|a| !config.exclude.iter().any(|x| x.matches(a)));
 
-    canvas_courses.retain(|x| {
-        !config.exclude.iter().any(|y| match y {
-            Exclusion::ByClassId { class_id } => class_id == &x.id,
-            _ => false,
-        })
-    });
-
-    canvas_courses.sort_by_key(|x| x.name.clone());
-
-    let canvas_assignments = try_join_all(canvas_courses.into_iter().map(|x| async move {
-        progress
-            .wrap(
-                &format!("Loading assignments for {}", x.name),
-                fetch::<Vec<CanvasAssignment>>(
-                    config,
-                    &format!(
-                        "/api/v1/courses/{}/assignments?per_page=10000&include=submission",
-                        x.id
-                    ),
-                ),
-            )
-            .await
-            .map(|c| (x, c))
-    }))
-    .await?;
-    Ok(canvas_assignments)
+    loaded.items.sort_by_key(|x| Reverse(x.due_at));
+
+    Ok(loaded)
 }
 
-async fn load_gradescope(
-    progress: &Progress,
-    config: &config::Config,
-) -> Result<Vec<(GradescopeCourse, Vec<GradescopeAssignment>)>> {
-    if config.gradescope_cookie.is_none() {
-        return Ok(vec![]);
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        matchers::{method, path, query_param, query_param_is_missing},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    fn page(items: &str, link: Option<String>) -> ResponseTemplate {
+        let response = ResponseTemplate::new(200).set_body_string(items);
+        match link {
+            Some(link) => response.insert_header("Link", link.as_str()),
+            None => response,
+        }
     }
 
-    let gradescope_courses = progress
-        .wrap("Loading Gradescope courses", async move {
-            load_courses(config).await
-        })
-        .await?;
-    let gradescope_assignments: Vec<(GradescopeCourse, Vec<GradescopeAssignment>)> =
-        try_join_all(gradescope_courses.into_iter().map(|x| async move {
-            progress
-                .wrap(&format!("Loading assignments for {}", x.name), async move {
-                    let assignments = load_assignments_for_course(config, x.id).await?;
-                    Ok((x.clone(), assignments)) as Result<_>
-                })
-                .await
-        }))
-        .await?;
-    Ok(gradescope_assignments)
+    #[test]
+    fn parses_link_header() {
+        let links = parse_link_header(Some(
+            "<https://canvas.test/api/v1/courses?page=2&per_page=10>; rel=\"next\", \
+             <https://canvas.test/api/v1/courses?page=1&per_page=10>; rel=\"first\", \
+             <https://canvas.test/api/v1/courses?page=5&per_page=10>; rel=\"last\"",
+        ));
+        assert_eq!(links.len(), 3);
+        assert_eq!(page_number(&links["next"]), Some(2));
+        assert_eq!(page_number(&links["last"]), Some(5));
+        assert_eq!(
+            with_page(&links["next"], 4).as_str(),
+            "https://canvas.test/api/v1/courses?page=4&per_page=10"
+        );
+
+        assert!(parse_link_header(None).is_empty());
+        assert!(parse_link_header(Some("garbage")).is_empty());
+        let bookmark = parse_link_header(Some(
+            "<https://canvas.test/api/v1/planner/items?page=bookmark:WzE1XQ>; rel=\"next\"",
+        ));
+        assert_eq!(page_number(&bookmark["next"]), None);
+    }
+
+    #[tokio::test]
+    async fn fetches_numbered_pages_concurrently() {
+        let server = MockServer::start().await;
+        let config = config::test_config(&server.uri());
+        let url = |page| format!("{}/api/v1/items?per_page=2&page={}", server.uri(), page);
+
+        Mock::given(method("GET"))
+            .and(path("/api/v1/items"))
+            .and(query_param_is_missing("page"))
+            .respond_with(page(
+                "[1, 2]",
+                Some(format!(
+                    "<{}>; rel=\"next\", <{}>; rel=\"last\"",
+                    url(2),
+                    url(3)
+                )),
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+        for (number, items) in [(2, "[3, 4]"), (3, "[5]")] {
+            Mock::given(method("GET"))
+                .and(path("/api/v1/items"))
+                .and(query_param("page", number.to_string()))
+                .respond_with(page(items, None))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+
+        let items: Vec<i64> = fetch_paginated(&config, "/api/v1/items?per_page=2")
+            .await
+            .unwrap();
+        assert_eq!(items, vec![1, 2, 3, 4, 5]);
+    }
+
+    #[tokio::test]
+    async fn follows_bookmark_pages_in_order() {
+        let server = MockServer::start().await;
+        let config = config::test_config(&server.uri());
+        let url = |bookmark| format!("{}/api/v1/items?page=bookmark:{}", server.uri(), bookmark);
+
+        Mock::given(method("GET"))
+            .and(path("/api/v1/items"))
+            .and(query_param_is_missing("page"))
+            .respond_with(page("[1]", Some(format!("<{}>; rel=\"next\"", url("b")))))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(query_param("page", "bookmark:b"))
+            .respond_with(page("[2]", Some(format!("<{}>; rel=\"next\"", url("c")))))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(query_param("page", "bookmark:c"))
+            .respond_with(page("[3]", None))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let items: Vec<i64> = fetch_paginated(&config, "/api/v1/items").await.unwrap();
+        assert_eq!(items, vec![1, 2, 3]);
+    }
 }
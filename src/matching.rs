@@ -0,0 +1,392 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    handle::{canvas_id, gradescope_id},
+    source::{Course, Item, Status},
+};
+
+/// How far apart due dates can be for two assignments to still be the same one.
+const MAX_DUE_HOURS: i64 = 36;
+
+/// How alike two names have to be, from 0 to 1.
+const MIN_NAME_SIMILARITY: f64 = 0.7;
+
+/// Settings for pairing Canvas assignments with the Gradescope assignments they launch.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Matching {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Courses to pair even when their names are too different to be paired automatically.
+    #[serde(default)]
+    pub courses: Vec<CourseMapping>,
+}
+
+impl Default for Matching {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            courses: vec![],
+        }
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CourseMapping {
+    pub canvas: i64,
+    pub gradescope: i64,
+}
+
+/// Lowercase words of a name, with numbers split off and common abbreviations spelled out, so
+/// that "HW3" and "Homework 3" come out the same.
+fn words(name: &str) -> Vec<String> {
+    let mut spaced = String::new();
+    let mut previous: Option<char> = None;
+    for c in name.to_lowercase().chars() {
+        if !c.is_alphanumeric() {
+            spaced.push(' ');
+        } else {
+            if previous.is_some_and(|x| x.is_ascii_digit() != c.is_ascii_digit()) {
+                spaced.push(' ');
+            }
+            spaced.push(c);
+        }
+        previous = Some(c).filter(|x| x.is_alphanumeric());
+    }
+
+    spaced
+        .split_whitespace()
+        .flat_map(|word| match word {
+            "hw" => vec!["homework"],
+            "proj" => vec!["project"],
+            "ps" | "pset" => vec!["problem", "set"],
+            "gradescope" | "submission" | "submit" | "the" | "a" | "on" | "to" => vec![],
+            // Leading zeros would make "Lab 01" and "Lab 1" differ.
+            word if word.chars().all(|x| x.is_ascii_digit()) => {
+                vec![word.trim_start_matches('0')]
+            }
+            word => vec![word],
+        })
+        .map(|x| x.to_string())
+        .collect()
+}
+
+fn numbers(words: &HashSet<String>) -> HashSet<&String> {
+    words
+        .iter()
+        .filter(|x| x.chars().all(|x| x.is_ascii_digit()))
+        .collect()
+}
+
+/// Dice similarity of the words in two names. Names that mention different numbers never match,
+/// since "Homework 3" and "Homework 4" are otherwise nearly the same.
+fn similarity(a: &str, b: &str) -> f64 {
+    let a: HashSet<_> = words(a).into_iter().collect();
+    let b: HashSet<_> = words(b).into_iter().collect();
+    if a.is_empty() || b.is_empty() || numbers(&a) != numbers(&b) {
+        return 0.0;
+    }
+    2.0 * a.intersection(&b).count() as f64 / (a.len() + b.len()) as f64
+}
+
+/// Whether the courses look like the same class, e.g. "CS 161" and "CS 161: Operating Systems".
+///
+/// Courses named in a mapping only pair as mapped, since one Canvas course can have several
+/// Gradescope courses, such as a lecture and a lab.
+pub fn same_course(canvas: &Course, gradescope: &Course, config: &Matching) -> bool {
+    let canvas_id = canvas_id(&canvas.key, "course");
+    let gradescope_id = gradescope_id(&gradescope.key, "course");
+    if config
+        .courses
+        .iter()
+        .any(|x| Some(x.canvas) == canvas_id && Some(x.gradescope) == gradescope_id)
+    {
+        return true;
+    }
+    if config
+        .courses
+        .iter()
+        .any(|x| Some(x.canvas) == canvas_id || Some(x.gradescope) == gradescope_id)
+    {
+        return false;
+    }
+
+    let a: HashSet<_> = words(&canvas.name).into_iter().collect();
+    let b: HashSet<_> = words(&gradescope.name).into_iter().collect();
+    let (na, nb) = (numbers(&a), numbers(&b));
+    if !na.is_empty() && !nb.is_empty() && na.is_disjoint(&nb) {
+        return false;
+    }
+    let smaller = a.len().min(b.len());
+    smaller > 0 && a.intersection(&b).count() as f64 / smaller as f64 > 0.5
+}
+
+/// How well a Canvas and a Gradescope item match, or `None` if they are not the same assignment.
+fn score(canvas: &Item, gradescope: &Item, config: &Matching) -> Option<f64> {
+    if !same_course(&canvas.course, &gradescope.course, config) {
+        return None;
+    }
+
+    let similarity = similarity(&canvas.title, &gradescope.title);
+    match (canvas.due_at, gradescope.due_at) {
+        (Some(a), Some(b)) => {
+            let hours = (a - b).num_hours().abs();
+            if similarity < MIN_NAME_SIMILARITY || hours > MAX_DUE_HOURS {
+                return None;
+            }
+            Some(similarity - 0.2 * hours as f64 / MAX_DUE_HOURS as f64)
+        }
+        // Without dates to compare, only nearly identical names are trusted.
+        _ if similarity >= 0.9 => Some(similarity - 0.2),
+        _ => None,
+    }
+}
+
+/// Merges Canvas assignments with the Gradescope assignments they stand for.
+///
+/// The merged item keeps the Canvas identity, but takes Gradescope's status and due date since
+/// that is where the work is turned in, and links to both. It remembers the Gradescope key, so
+/// exclusions and inclusions of either one apply to it.
+pub fn merge_duplicates(items: Vec<Item>, config: &Matching) -> Vec<Item> {
+    if !config.enabled {
+        return items;
+    }
+
+    let is_canvas = |x: &Item| x.key.starts_with("canvas:assignment:");
+    let is_gradescope = |x: &Item| x.key.starts_with("gradescope:assignment:");
+
+    let mut candidates = vec![];
+    for (i, canvas) in items.iter().enumerate().filter(|(_, x)| is_canvas(x)) {
+        for (j, gradescope) in items.iter().enumerate().filter(|(_, x)| is_gradescope(x)) {
+            if let Some(score) = score(canvas, gradescope, config) {
+                candidates.push((score, i, j));
+            }
+        }
+    }
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let mut paired = vec![None; items.len()];
+    let mut used = HashSet::new();
+    for (_, i, j) in candidates {
+        if used.contains(&i) || used.contains(&j) {
+            continue;
+        }
+        used.insert(i);
+        used.insert(j);
+        paired[i] = Some(j);
+    }
+
+    let mut merged = vec![];
+    for (i, item) in items.iter().enumerate() {
+        if let Some(j) = paired[i] {
+            let gradescope = &items[j];
+            let mut item = item.clone();
+            item.status = match item.status {
+                Status::Done => Status::Done,
+                _ => gradescope.status,
+            };
+            item.due_at = gradescope.due_at.or(item.due_at);
+            item.late_due_at = gradescope.late_due_at;
+            item.other_urls.push(gradescope.url.clone());
+            item.other_keys.push(gradescope.key.clone());
+            merged.push(item);
+        } else if !used.contains(&i) {
+            merged.push(item.clone());
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration, Local, TimeZone};
+
+    use super::*;
+    use crate::{
+        config::{Exclusion, Inclusion},
+        source::ItemKind,
+    };
+
+    fn due() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 9, 9, 23, 59, 0).unwrap()
+    }
+
+    fn course(key: &str, name: &str) -> Course {
+        Course {
+            key: key.into(),
+            name: name.into(),
+        }
+    }
+
+    fn item(key: &str, course: Course, title: &str, due_at: DateTime<Local>) -> Item {
+        Item {
+            key: key.into(),
+            kind: ItemKind::Assignment,
+            course,
+            title: title.into(),
+            due_at: Some(due_at),
+            late_due_at: None,
+            status: Status::Pending,
+            points: None,
+            url: format!("https://example.com/{}", key.replace(':', "/")),
+            other_urls: vec![],
+            other_keys: vec![],
+            submission_kinds: vec![],
+            locked: false,
+        }
+    }
+
+    fn mapping(canvas: i64, gradescope: i64) -> CourseMapping {
+        CourseMapping { canvas, gradescope }
+    }
+
+    #[test]
+    fn abbreviations_and_leading_zeros_are_the_same_words() {
+        assert_eq!(words("HW3"), words("Homework 3"));
+        assert_eq!(words("Lab 01"), words("Lab 1"));
+        assert_eq!(words("PS2 (Gradescope submission)"), words("Problem Set 2"));
+        assert_eq!(words("Proj1: Hog"), vec!["project", "1", "hog"]);
+    }
+
+    #[test]
+    fn names_with_different_numbers_never_match() {
+        assert_eq!(similarity("HW3", "Homework 3"), 1.0);
+        assert_eq!(similarity("Lab 01", "Lab 1"), 1.0);
+        assert_eq!(similarity("Homework 3", "Homework 4"), 0.0);
+        assert_eq!(similarity("Lab 1", "Lab 10"), 0.0);
+        assert_eq!(similarity("", "Lab 1"), 0.0);
+        let partial = similarity("Project 1: Hog", "Project 1");
+        assert!(partial > MIN_NAME_SIMILARITY && partial < 1.0);
+    }
+
+    #[test]
+    fn courses_pair_by_name_or_mapping() {
+        let cs161 = course("canvas:course:1", "CS 161: Operating Systems");
+        let lecture = course("gradescope:course:5", "CS 161");
+        let lab = course("gradescope:course:6", "CS 161 Lab");
+        let other = course("gradescope:course:7", "CS 162");
+        let unmapped = Matching::default();
+        assert!(same_course(&cs161, &lecture, &unmapped));
+        assert!(!same_course(&cs161, &other, &unmapped));
+
+        let mapped = Matching {
+            enabled: true,
+            courses: vec![mapping(1, 5), mapping(1, 6)],
+        };
+        assert!(same_course(&cs161, &lecture, &mapped));
+        assert!(same_course(&cs161, &lab, &mapped));
+        // Mapped courses do not fall back to their names.
+        let renamed = course("gradescope:course:8", "CS 161");
+        assert!(!same_course(&cs161, &renamed, &mapped));
+        let math = course("canvas:course:2", "Math 54");
+        let math_gradescope = course("gradescope:course:9", "Math 54");
+        assert!(same_course(&math, &math_gradescope, &mapped));
+        assert!(!same_course(&math, &lecture, &mapped));
+    }
+
+    #[test]
+    fn merged_items_take_the_gradescope_status_and_dates() {
+        let canvas_course = course("canvas:course:1", "CS 161");
+        let gradescope_course = course("gradescope:course:5", "CS 161");
+        let late_due_at = due() + Duration::days(2);
+        let mut gradescope = item(
+            "gradescope:assignment:456",
+            gradescope_course.clone(),
+            "HW3",
+            due() + Duration::hours(1),
+        );
+        gradescope.status = Status::Submitted;
+        gradescope.late_due_at = Some(late_due_at);
+        let items = vec![
+            item(
+                "canvas:assignment:3",
+                canvas_course.clone(),
+                "Homework 3",
+                due(),
+            ),
+            item("canvas:assignment:4", canvas_course, "Homework 4", due()),
+            gradescope,
+            item(
+                "gradescope:assignment:457",
+                gradescope_course,
+                "Homework 5",
+                due(),
+            ),
+        ];
+
+        let merged = merge_duplicates(items, &Matching::default());
+        let keys: Vec<_> = merged.iter().map(|x| x.key.as_str()).collect();
+        assert_eq!(
+            keys,
+            vec![
+                "canvas:assignment:3",
+                "canvas:assignment:4",
+                "gradescope:assignment:457"
+            ]
+        );
+        let homework = &merged[0];
+        assert_eq!(homework.status, Status::Submitted);
+        assert_eq!(homework.due_at, Some(due() + Duration::hours(1)));
+        assert_eq!(homework.late_due_at, Some(late_due_at));
+        assert_eq!(
+            homework.other_urls,
+            vec!["https://example.com/gradescope/assignment/456"]
+        );
+        assert_eq!(homework.other_keys, vec!["gradescope:assignment:456"]);
+    }
+
+    #[test]
+    fn done_in_the_planner_stays_done() {
+        let mut canvas = item(
+            "canvas:assignment:3",
+            course("canvas:course:1", "CS 161"),
+            "Homework 3",
+            due(),
+        );
+        canvas.status = Status::Done;
+        let gradescope = item(
+            "gradescope:assignment:456",
+            course("gradescope:course:5", "CS 161"),
+            "Homework 3",
+            due(),
+        );
+        let merged = merge_duplicates(vec![canvas, gradescope], &Matching::default());
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].status, Status::Done);
+    }
+
+    #[test]
+    fn either_key_excludes_or_includes_a_merged_item() {
+        let canvas = item(
+            "canvas:assignment:3",
+            course("canvas:course:1", "CS 161"),
+            "Homework 3",
+            due(),
+        );
+        let gradescope = item(
+            "gradescope:assignment:456",
+            course("gradescope:course:5", "CS 161"),
+            "HW 3",
+            due(),
+        );
+        let merged = merge_duplicates(vec![canvas, gradescope], &Matching::default());
+        assert_eq!(merged.len(), 1);
+        let merged = &merged[0];
+
+        assert!(Exclusion::ByAssignmentId { assignment_id: 3 }.matches(merged));
+        assert!(Exclusion::ByGradescopeAssignmentId {
+            gradescope_assignment_id: 456
+        }
+        .matches(merged));
+        assert!(!Exclusion::ByAssignmentId { assignment_id: 456 }.matches(merged));
+        assert!(Inclusion::ByGradescopeAssignmentId {
+            gradescope_assignment_id: 456
+        }
+        .matches(merged));
+    }
+}
@@ -0,0 +1,517 @@
+use std::{
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use async_trait::async_trait;
+use chrono::{Duration, Local};
+use color_eyre::{eyre::eyre, Result, Section};
+use colored::Colorize;
+use reqwest::{Method, Url};
+use serde_json::json;
+
+use crate::{
+    cache,
+    canvas::lookup_assignment,
+    canvas_api::{PlannerItem, PlannerOverride},
+    canvas_url, config, fetch_paginated,
+    handle::{self, Target},
+    progress::Progress,
+    send_json,
+    source::{AssignmentSource, Course, Item, ItemKind, Loaded, Status, SubmissionKind},
+};
+
+/// How far back to look for planner items when `hide_overdue_after_days` is not set.
+const DEFAULT_LOOKBACK_DAYS: i64 = 28;
+
+const OVERRIDES_URL: &str = "/api/v1/planner/overrides?per_page=100";
+
+static OVERRIDES_WARNED: AtomicBool = AtomicBool::new(false);
+
+/// Loads the items the user has marked as done (or not done) in the planner.
+pub async fn load_overrides(config: &config::Config) -> Result<Vec<PlannerOverride>> {
+    fetch_paginated(config, OVERRIDES_URL).await
+}
+
+/// Like [`load_overrides`], but falls back to no overrides with a warning, since scoped tokens
+/// and instances with the planner turned off cannot read them. Both Canvas sources call this,
+/// so the warning is only printed once.
+pub async fn load_overrides_or_warn(
+    progress: &Progress,
+    config: &config::Config,
+) -> Vec<PlannerOverride> {
+    match load_overrides(config).await {
+        Ok(overrides) => overrides,
+        Err(error) => {
+            if !OVERRIDES_WARNED.swap(true, Ordering::SeqCst) {
+                progress.println(&format!(
+                    "{}\n  {:#}\n",
+                    "Warning: items marked as done in the planner failed to load"
+                        .yellow()
+                        .bold(),
+                    error
+                ));
+            }
+            vec![]
+        }
+    }
+}
+
+/// Whether the planner item is marked as done.
+pub fn is_marked_done(overrides: &[PlannerOverride], plannable_type: &str, id: i64) -> bool {
+    overrides
+        .iter()
+        .any(|x| x.plannable_type == plannable_type && x.plannable_id == id && x.marked_complete)
+}
+
+/// Marks an item as done or not done, the same way the checkbox in the Canvas planner does.
+///
+/// `plannables` are the types and ids the planner may know the item by, preferred first.
+pub async fn set_done(
+    config: &config::Config,
+    plannables: &[(ItemKind, i64)],
+    done: bool,
+) -> Result<()> {
+    // The cached list may predate changes made in the browser.
+    cache::invalidate(&canvas_url(config, OVERRIDES_URL)).await;
+    let overrides = load_overrides(config).await?;
+    let existing: Vec<_> = overrides
+        .iter()
+        .filter(|x| {
+            plannables
+                .iter()
+                .any(|&(kind, id)| x.plannable_type == plannable_type(kind) && x.plannable_id == id)
+        })
+        .collect();
+
+    match (&existing[..], plannables.first()) {
+        ([], Some(&(kind, id))) if done => {
+            send_json::<PlannerOverride>(
+                config,
+                Method::POST,
+                "/api/v1/planner/overrides",
+                &json!({
+                    "plannable_type": plannable_type(kind),
+                    "plannable_id": id,
+                    "marked_complete": true,
+                }),
+            )
+            .await?;
+        }
+        ([], Some(&(kind, id))) => {
+            return Err(eyre!(
+                "{} {} is not marked as done in the planner",
+                kind.label(),
+                id
+            ))
+            .suggestion(
+                "Only items checked off by `canvas done` or in the Canvas planner can be unchecked",
+            );
+        }
+        _ => {
+            for existing in existing {
+                send_json::<PlannerOverride>(
+                    config,
+                    Method::PUT,
+                    &format!("/api/v1/planner/overrides/{}", existing.id),
+                    &json!({ "marked_complete": done }),
+                )
+                .await?;
+            }
+        }
+    }
+
+    cache::invalidate(&canvas_url(config, OVERRIDES_URL)).await;
+
+    Ok(())
+}
+
+/// Finds the planner types and ids of an item argument, preferred first. `kind` is used for raw
+/// ids.
+///
+/// Graded quizzes and discussions are listed under their assignment, but the Canvas planner
+/// marks them done by the quiz or topic, so those come before the assignment itself.
+pub async fn resolve_plannable(
+    config: &config::Config,
+    query: &str,
+    kind: Option<ItemKind>,
+) -> Result<Vec<(ItemKind, i64)>> {
+    let (kind, id) = match handle::resolve(config, query).await? {
+        Target::Key { key, .. } => {
+            let (plannable_type, id) = key
+                .strip_prefix("canvas:")
+                .and_then(|x| x.split_once(':'))
+                .and_then(|(plannable_type, id)| Some((plannable_type, id.parse().ok()?)))
+                .ok_or_else(|| eyre!("`{}` is not in the Canvas planner", query))?;
+            // Courses and anything else that is not a to-do would otherwise become an override
+            // for an unrelated assignment with the same id.
+            let kind = self::kind(plannable_type)
+                .ok_or_else(|| {
+                    eyre!(
+                        "`{}` is a {}, which cannot be marked as done",
+                        query,
+                        plannable_type.replace('_', " ")
+                    )
+                })
+                .suggestion("Pass an assignment, quiz, discussion, page, event or note")?;
+            (kind, id)
+        }
+        Target::Id(id) => (kind.unwrap_or(ItemKind::Assignment), id),
+        Target::Name(_) => {
+            return Err(eyre!("No item in the last listing matches `{}`", query))
+                .suggestion("Run `canvas todo` first, or pass an id with --type")
+        }
+    };
+    if kind != ItemKind::Assignment {
+        return Ok(vec![(kind, id)]);
+    }
+
+    let (_, assignment) = lookup_assignment(config, query).await?;
+    let mut plannables = vec![];
+    if let Some(quiz_id) = assignment.quiz_id {
+        plannables.push((ItemKind::Quiz, quiz_id));
+    }
+    if let Some(topic) = &assignment.discussion_topic {
+        plannables.push((ItemKind::Discussion, topic.id));
+    }
+    plannables.push((ItemKind::Assignment, assignment.id));
+    Ok(plannables)
+}
+
+fn plannable_type(kind: ItemKind) -> &'static str {
+    match kind {
+        ItemKind::Assignment => "assignment",
+        ItemKind::Quiz => "quiz",
+        ItemKind::Discussion => "discussion_topic",
+        ItemKind::Page => "wiki_page",
+        ItemKind::Event => "calendar_event",
+        ItemKind::Note => "planner_note",
+    }
+}
+
+/// Quizzes, ungraded discussions, pages, calendar events and notes from the Canvas planner.
+pub struct PlannerSource;
+
+#[async_trait]
+impl AssignmentSource for PlannerSource {
+    fn name(&self) -> &'static str {
+        "Canvas planner"
+    }
+
+    async fn load(&self, progress: &Progress, config: &config::Config) -> Result<Loaded> {
+        let start = Local::now()
+            - Duration::days(
+                config
+                    .hide_overdue_after_days
+                    .unwrap_or(DEFAULT_LOOKBACK_DAYS),
+            );
+        let url = format!(
+            "/api/v1/planner/items?per_page=100&start_date={}",
+            start.format("%Y-%m-%d")
+        );
+        let items = fetch_paginated::<PlannerItem>(config, &url);
+        let (items, overrides) = progress
+            .wrap("Loading planner", async {
+                tokio::join!(items, load_overrides_or_warn(progress, config))
+            })
+            .await;
+        let items = items?;
+
+        Ok(Loaded {
+            items: items
+                .iter()
+                .filter_map(|x| to_item(config, &overrides, x))
+                .collect(),
+            failures: vec![],
+            successes: 1,
+        })
+    }
+}
+
+/// The kind of to-do a planner type is. Announcements, peer review requests and anything newer
+/// are in the planner too, but are not to-dos.
+fn kind(plannable_type: &str) -> Option<ItemKind> {
+    match plannable_type {
+        "assignment" => Some(ItemKind::Assignment),
+        "quiz" => Some(ItemKind::Quiz),
+        "discussion_topic" => Some(ItemKind::Discussion),
+        "wiki_page" => Some(ItemKind::Page),
+        "calendar_event" => Some(ItemKind::Event),
+        "planner_note" => Some(ItemKind::Note),
+        _ => None,
+    }
+}
+
+fn to_item(
+    config: &config::Config,
+    overrides: &[PlannerOverride],
+    item: &PlannerItem,
+) -> Option<Item> {
+    let kind = kind(&item.plannable_type)?;
+
+    // Graded quizzes and discussions are also assignments, so they share the assignment's key.
+    let assignment_id = match item.plannable_type.as_str() {
+        "assignment" => Some(item.plannable_id),
+        _ => item.plannable.assignment_id,
+    };
+    let key = match assignment_id {
+        Some(id) => format!("canvas:assignment:{}", id),
+        None => format!("canvas:{}:{}", item.plannable_type, item.plannable_id),
+    };
+
+    let submitted = item
+        .submissions
+        .get("submitted")
+        .and_then(|x| x.as_bool())
+        .unwrap_or(false);
+
+    Some(Item {
+        key,
+        kind,
+        course: match item.course_id {
+            Some(id) => Course {
+                key: format!("canvas:course:{}", id),
+                name: item.context_name.clone().unwrap_or_default(),
+            },
+            None => Course {
+                key: "canvas:user".into(),
+                name: "Personal".into(),
+            },
+        },
+        title: item.plannable.title.trim().to_string(),
+        due_at: item
+            .plannable
+            .due_at
+            .or(item.plannable.todo_date)
+            .or(item.plannable_date),
+        status: if is_marked_done(overrides, &item.plannable_type, item.plannable_id) {
+            Status::Done
+        } else if submitted {
+            Status::Submitted
+        } else {
+            Status::Pending
+        },
+        late_due_at: None,
+        points: item.plannable.points_possible,
+        url: item
+            .html_url
+            .as_ref()
+            .and_then(|x| Url::from_str(&config.canvas_url).ok()?.join(x).ok())
+            .map(|x| x.to_string())
+            .unwrap_or_else(|| config.canvas_url.clone()),
+        submission_kinds: match kind {
+            ItemKind::Quiz => vec![SubmissionKind::Quiz],
+            ItemKind::Discussion => vec![SubmissionKind::Discussion],
+            _ => vec![],
+        },
+        locked: false,
+        other_urls: vec![],
+        other_keys: vec![],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+    use wiremock::{
+        matchers::{body_json, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+    use crate::config::test_config;
+
+    async fn serve_overrides(server: &MockServer, overrides: Value) {
+        Mock::given(method("GET"))
+            .and(path("/api/v1/planner/overrides"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(overrides))
+            .mount(server)
+            .await;
+    }
+
+    fn saved_override(id: i64, marked_complete: bool) -> Value {
+        json!({
+            "id": id,
+            "plannable_type": "quiz",
+            "plannable_id": 7,
+            "marked_complete": marked_complete,
+            "dismissed": false,
+        })
+    }
+
+    #[tokio::test]
+    async fn done_creates_an_override() {
+        let server = MockServer::start().await;
+        serve_overrides(&server, json!([])).await;
+        Mock::given(method("POST"))
+            .and(path("/api/v1/planner/overrides"))
+            .and(body_json(json!({
+                "plannable_type": "quiz",
+                "plannable_id": 7,
+                "marked_complete": true,
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(saved_override(55, true)))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let config = test_config(&server.uri());
+        set_done(&config, &[(ItemKind::Quiz, 7)], true)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn existing_override_is_updated() {
+        let server = MockServer::start().await;
+        serve_overrides(&server, json!([saved_override(55, true)])).await;
+        Mock::given(method("PUT"))
+            .and(path("/api/v1/planner/overrides/55"))
+            .and(body_json(json!({ "marked_complete": false })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(saved_override(55, false)))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let config = test_config(&server.uri());
+        set_done(&config, &[(ItemKind::Quiz, 7)], false)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn undone_without_an_override_is_an_error() {
+        let server = MockServer::start().await;
+        serve_overrides(&server, json!([])).await;
+        for verb in ["POST", "PUT"] {
+            Mock::given(method(verb))
+                .respond_with(ResponseTemplate::new(500))
+                .expect(0)
+                .mount(&server)
+                .await;
+        }
+
+        let config = test_config(&server.uri());
+        let error = set_done(&config, &[(ItemKind::Quiz, 7)], false)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Quiz 7 is not marked as done in the planner"
+        );
+    }
+
+    #[tokio::test]
+    async fn quizzes_listed_as_assignments_use_the_quiz_override() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1/courses/52817"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({ "id": 52817, "name": "CS 61A" })),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1/courses/52817/assignments/3"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": 3,
+                "name": "Quiz 1",
+                "course_id": 52817,
+                "html_url": format!("{}/courses/52817/assignments/3", server.uri()),
+                "quiz_id": 7,
+            })))
+            .mount(&server)
+            .await;
+        // Marked done in the Canvas planner, which knows it by the quiz.
+        serve_overrides(&server, json!([saved_override(55, true)])).await;
+        Mock::given(method("PUT"))
+            .and(path("/api/v1/planner/overrides/55"))
+            .and(body_json(json!({ "marked_complete": false })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(saved_override(55, false)))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut config = test_config(&server.uri());
+        config.gradescope_url = "https://www.gradescope.com/".into();
+        let plannables = resolve_plannable(
+            &config,
+            &format!("{}/courses/52817/assignments/3", server.uri()),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            plannables,
+            vec![(ItemKind::Quiz, 7), (ItemKind::Assignment, 3)]
+        );
+        set_done(&config, &plannables, false).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn only_plannable_links_resolve() {
+        let mut config = test_config("https://canvas.example.edu");
+        config.gradescope_url = "https://www.gradescope.com/".into();
+        let plannables = resolve_plannable(
+            &config,
+            "https://canvas.example.edu/courses/52817/quizzes/7",
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(plannables, vec![(ItemKind::Quiz, 7)]);
+
+        let error = resolve_plannable(&config, "https://canvas.example.edu/courses/123", None)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("is a course"));
+    }
+
+    #[tokio::test]
+    async fn overrides_failing_to_load_are_a_warning() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1/planner/overrides"))
+            .respond_with(ResponseTemplate::new(403))
+            .mount(&server)
+            .await;
+
+        let config = test_config(&server.uri());
+        let progress = Progress::new();
+        assert!(load_overrides_or_warn(&progress, &config).await.is_empty());
+        progress.finish();
+    }
+
+    fn planner_item(plannable_type: &str) -> PlannerItem {
+        PlannerItem {
+            plannable_id: 7,
+            plannable_type: plannable_type.into(),
+            course_id: Some(52817),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn only_to_dos_become_items() {
+        let config = test_config("https://canvas.example.edu");
+        for (plannable_type, kind) in [
+            ("assignment", ItemKind::Assignment),
+            ("quiz", ItemKind::Quiz),
+            ("discussion_topic", ItemKind::Discussion),
+            ("wiki_page", ItemKind::Page),
+            ("calendar_event", ItemKind::Event),
+            ("planner_note", ItemKind::Note),
+        ] {
+            let item = to_item(&config, &[], &planner_item(plannable_type)).unwrap();
+            assert_eq!(item.kind, kind);
+        }
+        for plannable_type in ["announcement", "assessment_request", "something_new"] {
+            assert!(to_item(&config, &[], &planner_item(plannable_type)).is_none());
+        }
+    }
+}
@@ -48,6 +48,15 @@ impl Progress {
         o
     }
 
+    /// Prints a line above the progress bar, or on its own when the bar is hidden.
+    pub fn println(&self, msg: &str) {
+        if self.bar.is_hidden() {
+            eprintln!("{}", msg);
+        } else {
+            self.bar.println(msg);
+        }
+    }
+
     pub fn finish(self) {
         self.bar.finish_and_clear();
     }
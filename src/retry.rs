@@ -0,0 +1,139 @@
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use backoff::ExponentialBackoff;
+use color_eyre::{eyre::eyre, Report, Result};
+use lazy_static::lazy_static;
+use reqwest::{header::RETRY_AFTER, RequestBuilder, Response, StatusCode};
+
+use crate::config;
+
+/// Below this many remaining units Canvas is close to throttling us, so requests are spaced out.
+const RATE_LIMIT_LOW_WATER: f64 = 100.0;
+/// Canvas refills the rate limit bucket at roughly this many units per second.
+const RATE_LIMIT_RECOVERY_PER_SEC: f64 = 10.0;
+
+#[derive(Default)]
+struct RateLimit {
+    remaining: Option<f64>,
+    cost: f64,
+}
+
+lazy_static! {
+    static ref RATE_LIMIT: Mutex<RateLimit> = Mutex::new(RateLimit::default());
+}
+
+/// Sends an idempotent request, retrying transient failures with jittered exponential backoff.
+///
+/// `request` is called once per attempt, since a `RequestBuilder` cannot be reused.
+pub async fn send(
+    config: &config::Config,
+    request: impl Fn() -> RequestBuilder,
+) -> Result<Response> {
+    let policy = ExponentialBackoff {
+        max_elapsed_time: Some(Duration::from_secs(config.retry_budget_secs)),
+        ..Default::default()
+    };
+    let attempts = AtomicU32::new(0);
+
+    backoff::future::retry(policy, || async {
+        let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+        throttle().await;
+        match send_once(request()).await {
+            Ok(response) => Ok(response),
+            Err(backoff::Error::Transient { err, retry_after }) if attempt < config.max_retries => {
+                Err(backoff::Error::Transient { err, retry_after })
+            }
+            Err(backoff::Error::Transient { err, .. }) => Err(backoff::Error::Permanent(
+                err.wrap_err(format!("Giving up after {} attempts", attempt + 1)),
+            )),
+            Err(e) => Err(e),
+        }
+    })
+    .await
+}
+
+async fn send_once(request: RequestBuilder) -> Result<Response, backoff::Error<Report>> {
+    let response = request.send().await.map_err(|e| {
+        let transient = e.is_timeout() || e.is_connect() || e.is_request();
+        let e = Report::new(e).wrap_err("Unable to send request");
+        if transient {
+            backoff::Error::transient(e)
+        } else {
+            backoff::Error::Permanent(e)
+        }
+    })?;
+
+    record_rate_limit(&response);
+
+    let status = response.status();
+    if status.is_success() || status == StatusCode::NOT_MODIFIED {
+        return Ok(response);
+    }
+
+    let retry_after = response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| x.parse().ok())
+        .map(Duration::from_secs);
+    let err = response.error_for_status_ref().unwrap_err();
+
+    // Gradescope throttles with 429 and 503; both are worth waiting out.
+    if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+        return Err(backoff::Error::Transient {
+            err: Report::new(err).wrap_err("Server returned error"),
+            retry_after,
+        });
+    }
+
+    // Canvas reports throttling as a 403 with a plain text body.
+    if status == StatusCode::FORBIDDEN {
+        let body = response.text().await.unwrap_or_default();
+        if body.contains("Rate Limit Exceeded") {
+            return Err(backoff::Error::transient(eyre!("Rate limit exceeded")));
+        }
+    }
+
+    Err(backoff::Error::Permanent(
+        Report::new(err).wrap_err("Server returned error"),
+    ))
+}
+
+fn record_rate_limit(response: &Response) {
+    let header = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|x| x.to_str().ok())
+            .and_then(|x| x.parse::<f64>().ok())
+    };
+    if let Some(remaining) = header("X-Rate-Limit-Remaining") {
+        let mut rate_limit = RATE_LIMIT.lock().unwrap();
+        rate_limit.remaining = Some(remaining);
+        if let Some(cost) = header("X-Request-Cost") {
+            rate_limit.cost = cost;
+        }
+    }
+}
+
+async fn throttle() {
+    let delay = {
+        let rate_limit = RATE_LIMIT.lock().unwrap();
+        let low_water = RATE_LIMIT_LOW_WATER.max(rate_limit.cost * 2.0);
+        match rate_limit.remaining {
+            Some(remaining) if remaining < low_water => {
+                Some((low_water - remaining) / RATE_LIMIT_RECOVERY_PER_SEC)
+            }
+            _ => None,
+        }
+    };
+    if let Some(delay) = delay {
+        tokio::time::sleep(Duration::from_secs_f64(delay)).await;
+    }
+}
@@ -0,0 +1,166 @@
+use chrono::{DateTime, Local};
+use color_eyre::Result;
+use colored::Colorize;
+
+use crate::{
+    canvas::lookup_assignment,
+    canvas_api::{CanvasAssignment, Submission},
+    config, format_datetime, format_duration_full, html,
+    source::SubmissionKind,
+};
+
+fn print_field(label: &str, value: impl std::fmt::Display) {
+    println!("{:>12}  {}", label.bright_black(), value);
+}
+
+fn describe_time(time: DateTime<Local>) -> String {
+    format!(
+        "{} ({})",
+        format_datetime(time),
+        format_duration_full(Local::now(), time)
+    )
+}
+
+pub async fn run_show(config: &config::Config, query: &str) -> Result<()> {
+    let (course, assignment) = lookup_assignment(config, query).await?;
+
+    println!("{}", assignment.name.trim().bold().underline());
+    println!("{}", course.name);
+    println!();
+
+    print_assignment(&assignment);
+    if let Some(submission) = &assignment.submission {
+        println!();
+        print_submission(&assignment, submission);
+    }
+
+    if let Some(description) = assignment
+        .description
+        .as_deref()
+        .filter(|x| !x.trim().is_empty())
+    {
+        println!();
+        println!("{}", html::render(description));
+    }
+
+    println!();
+    println!("{}", assignment.html_url);
+
+    Ok(())
+}
+
+fn print_assignment(assignment: &CanvasAssignment) {
+    if let Some(due_at) = assignment.due_at {
+        print_field("Due", describe_time(due_at));
+    }
+    if let Some(unlock_at) = assignment.unlock_at {
+        print_field("Unlocks", describe_time(unlock_at));
+    }
+    if let Some(lock_at) = assignment.lock_at {
+        print_field("Locks", describe_time(lock_at));
+    }
+    if assignment.locked_for_user {
+        print_field("Locked", "yes".red());
+    }
+    if let Some(points) = assignment.points_possible {
+        print_field("Points", points);
+    }
+    if !assignment.submission_types.is_empty() {
+        print_field(
+            "Submit as",
+            assignment
+                .submission_types
+                .iter()
+                .map(|x| SubmissionKind::from_canvas(x).label())
+                .collect::<Vec<_>>()
+                .join(", "),
+        );
+    }
+
+    let used = assignment
+        .submission
+        .as_ref()
+        .and_then(|x| x.attempt)
+        .unwrap_or(0);
+    match assignment.allowed_attempts.filter(|x| *x > 0) {
+        Some(allowed) => print_field("Attempts", format!("{} of {} used", used, allowed)),
+        None if used > 0 => print_field("Attempts", format!("{} used, unlimited", used)),
+        None => {}
+    }
+
+    if assignment.peer_reviews {
+        let mut flags = vec![];
+        if let Some(count) = assignment.peer_review_count.filter(|x| *x > 0) {
+            flags.push(format!("{} required", count));
+        }
+        if assignment.automatic_peer_reviews {
+            flags.push("assigned automatically".into());
+        }
+        if assignment.anonymous_peer_reviews {
+            flags.push("anonymous".into());
+        }
+        print_field(
+            "Peer review",
+            if flags.is_empty() {
+                "yes".into()
+            } else {
+                flags.join(", ")
+            },
+        );
+    }
+    if assignment.omit_from_final_grade {
+        print_field("Grade", "not counted toward final grade");
+    }
+}
+
+fn print_submission(assignment: &CanvasAssignment, submission: &Submission) {
+    let state = match submission.workflow_state.as_deref() {
+        Some("unsubmitted") | None => "not submitted".bright_black().to_string(),
+        Some("submitted") => "submitted".green().to_string(),
+        Some("graded") => "graded".green().to_string(),
+        Some("pending_review") => "pending review".yellow().to_string(),
+        Some(other) => other.replace('_', " "),
+    };
+    print_field("Submission", state);
+
+    if let Some(submitted_at) = &submission.submitted_at {
+        let submitted_at = DateTime::parse_from_rfc3339(submitted_at)
+            .map(|x| format_datetime(x.with_timezone(&Local)))
+            .unwrap_or_else(|_| submitted_at.clone());
+        print_field("Submitted", submitted_at);
+    }
+    if submission.excused == Some(true) {
+        print_field("Score", "excused");
+    } else if let Some(score) = submission.score {
+        let points = assignment.points_possible.unwrap_or(0.0);
+        let grade = match &submission.grade {
+            Some(grade) if grade.parse::<f64>().is_err() => format!(" ({})", grade),
+            _ => "".into(),
+        };
+        print_field("Score", format!("{} / {}{}", score, points, grade));
+    }
+    if submission.late {
+        let mut late = "late".red().to_string();
+        if let Some(deducted) = submission.points_deducted.filter(|x| *x > 0.0) {
+            late += &format!(", -{} points", deducted);
+        }
+        print_field("Late", late);
+    }
+    if submission.missing {
+        print_field("Missing", "yes".red());
+    }
+    if !submission.attachments.is_empty() {
+        print_field(
+            "Files",
+            submission
+                .attachments
+                .iter()
+                .filter_map(|x| x.display_name.clone().or_else(|| x.filename.clone()))
+                .collect::<Vec<_>>()
+                .join(", "),
+        );
+    }
+    if let Some(url) = &submission.url {
+        print_field("URL", url);
+    }
+}
@@ -0,0 +1,329 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Local};
+use color_eyre::{eyre::eyre, Report, Result, Section};
+use colored::Colorize;
+
+use std::str::FromStr;
+
+use crate::{
+    canvas::CanvasSource, config, gradescope::GradescopeSource, planner::PlannerSource,
+    progress::Progress,
+};
+
+/// A place assignments come from, such as Canvas or Gradescope.
+#[async_trait]
+pub trait AssignmentSource: Send + Sync {
+    /// Name shown when the source fails to load.
+    fn name(&self) -> &'static str;
+
+    /// Loads every assignment, or fails if the source could not be reached at all.
+    async fn load(&self, progress: &Progress, config: &config::Config) -> Result<Loaded>;
+}
+
+/// Builds the sources listed in `sources` in the configuration file.
+pub fn enabled_sources(config: &config::Config) -> Result<Vec<Box<dyn AssignmentSource>>> {
+    config
+        .sources
+        .iter()
+        .map(|name| -> Result<Box<dyn AssignmentSource>> {
+            match name.as_str() {
+                "canvas" => Ok(Box::new(CanvasSource)),
+                "planner" => Ok(Box::new(PlannerSource)),
+                "gradescope" => Ok(Box::new(GradescopeSource)),
+                _ => Err(eyre!("Unknown source `{}`", name))
+                    .suggestion("Valid sources are `canvas`, `planner` and `gradescope`"),
+            }
+        })
+        .collect()
+}
+
+/// An assignment from any source, in the shape used for filtering and display.
+#[derive(Debug, Clone)]
+pub struct Item {
+    /// Identifies the item across all sources, e.g. `canvas:assignment:123`.
+    ///
+    /// Sources that see the same item give it the same key, so it is only shown once.
+    pub key: String,
+    pub kind: ItemKind,
+    pub course: Course,
+    pub title: String,
+    pub due_at: Option<DateTime<Local>>,
+    /// The end of the window in which late submissions are still accepted.
+    pub late_due_at: Option<DateTime<Local>>,
+    pub status: Status,
+    pub points: Option<f64>,
+    pub url: String,
+    /// Links to the same item elsewhere, such as on Gradescope.
+    pub other_urls: Vec<String>,
+    /// Keys of the same item elsewhere, such as the Gradescope assignment it was merged with.
+    pub other_keys: Vec<String>,
+    pub submission_kinds: Vec<SubmissionKind>,
+    pub locked: bool,
+}
+
+impl Item {
+    /// Whether the item is, or was merged with, the one with this key.
+    pub fn has_key(&self, key: &str) -> bool {
+        self.key == key || self.other_keys.iter().any(|x| x == key)
+    }
+
+    pub fn has_online_submission(&self) -> bool {
+        self.submission_kinds.iter().any(|x| x.is_online())
+    }
+
+    /// Submission types and points, as shown next to the title.
+    pub fn details(&self) -> String {
+        let kinds: Vec<_> = self
+            .submission_kinds
+            .iter()
+            .map(|x| {
+                if x.is_online() {
+                    x.label().purple().to_string()
+                } else {
+                    x.label().to_string()
+                }
+            })
+            .collect();
+        if kinds.is_empty() {
+            return self.kind.label().to_string();
+        }
+        match self.points {
+            Some(points) => format!("{} - {} points", kinds.join(", "), points),
+            None => kinds.join(", "),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Course {
+    /// Identifies the course across all sources, e.g. `gradescope:course:123`.
+    pub key: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemKind {
+    Assignment,
+    Quiz,
+    Discussion,
+    Page,
+    Event,
+    Note,
+}
+
+impl ItemKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            ItemKind::Assignment => "Assignment",
+            ItemKind::Quiz => "Quiz",
+            ItemKind::Discussion => "Discussion",
+            ItemKind::Page => "Page",
+            ItemKind::Event => "Event",
+            ItemKind::Note => "Note",
+        }
+    }
+}
+
+impl FromStr for ItemKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "assignment" => Ok(ItemKind::Assignment),
+            "quiz" => Ok(ItemKind::Quiz),
+            "discussion" => Ok(ItemKind::Discussion),
+            "page" => Ok(ItemKind::Page),
+            "event" => Ok(ItemKind::Event),
+            "note" => Ok(ItemKind::Note),
+            _ => Err(format!(
+                "unknown type `{}`, expected one of assignment, quiz, discussion, page, event, note",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Pending,
+    /// Something was submitted, but more is required before it is done.
+    InProgress,
+    Submitted,
+    /// Marked as done in the Canvas planner.
+    Done,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubmissionKind {
+    None,
+    OnPaper,
+    TextEntry,
+    Upload,
+    Url,
+    Quiz,
+    Discussion,
+    Media,
+    ExternalTool,
+    Gradescope,
+    Unknown,
+}
+
+impl SubmissionKind {
+    pub fn from_canvas(submission_type: &str) -> Self {
+        match submission_type {
+            "none" => SubmissionKind::None,
+            "on_paper" => SubmissionKind::OnPaper,
+            "online_text_entry" => SubmissionKind::TextEntry,
+            "online_upload" => SubmissionKind::Upload,
+            "online_url" => SubmissionKind::Url,
+            "online_quiz" => SubmissionKind::Quiz,
+            "discussion_topic" => SubmissionKind::Discussion,
+            "media_recording" => SubmissionKind::Media,
+            "external_tool" => SubmissionKind::ExternalTool,
+            _ => SubmissionKind::Unknown,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SubmissionKind::None => "No submission",
+            SubmissionKind::OnPaper => "On paper",
+            SubmissionKind::TextEntry => "Text entry",
+            SubmissionKind::Upload => "File upload",
+            SubmissionKind::Url => "Website URL",
+            SubmissionKind::Quiz => "Quiz",
+            SubmissionKind::Discussion => "Discussion",
+            SubmissionKind::Media => "Media recording",
+            SubmissionKind::ExternalTool => "External tool",
+            SubmissionKind::Gradescope => "Gradescope",
+            SubmissionKind::Unknown => "Unknown",
+        }
+    }
+
+    pub fn is_online(self) -> bool {
+        !matches!(self, SubmissionKind::None | SubmissionKind::OnPaper)
+    }
+}
+
+/// A course or source that could not be loaded.
+#[derive(Debug)]
+pub struct Failure {
+    pub source: String,
+    pub error: Report,
+}
+
+/// Whether every course and source loaded, so scripts can tell partial results apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadStatus {
+    Complete,
+    Partial,
+}
+
+impl LoadStatus {
+    pub fn exit(self) {
+        if self == LoadStatus::Partial {
+            std::process::exit(2);
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Loaded {
+    pub items: Vec<Item>,
+    pub failures: Vec<Failure>,
+    /// Number of courses, or sources without courses, that loaded successfully.
+    pub successes: usize,
+}
+
+impl Loaded {
+    /// Collects per-course results into the items that loaded and the courses that failed.
+    ///
+    /// The course list on its own does not count as a success, so that a source whose courses all
+    /// failed is not mistaken for a partial result. An empty course list does.
+    pub fn from_courses(results: Vec<Result<Vec<Item>, Failure>>) -> Self {
+        let mut loaded = Loaded {
+            successes: if results.is_empty() { 1 } else { 0 },
+            ..Default::default()
+        };
+        for result in results {
+            match result {
+                Ok(items) => {
+                    loaded.items.extend(items);
+                    loaded.successes += 1;
+                }
+                Err(failure) => loaded.failures.push(failure),
+            }
+        }
+        loaded
+    }
+
+    pub fn extend(&mut self, other: Loaded) {
+        self.items.extend(other.items);
+        self.failures.extend(other.failures);
+        self.successes += other.successes;
+    }
+
+    /// Prints a warning for each failure, and fails outright if nothing could be loaded.
+    pub fn report_failures(&self) -> Result<LoadStatus> {
+        if self.failures.is_empty() {
+            return Ok(LoadStatus::Complete);
+        }
+
+        if self.successes == 0 {
+            let mut error = eyre!("Unable to load any assignments");
+            for failure in &self.failures {
+                error = error.section(format!("{}: {:#}", failure.source, failure.error));
+            }
+            return Err(error);
+        }
+
+        eprintln!(
+            "{}",
+            format!(
+                "Warning: {} source{} failed to load",
+                self.failures.len(),
+                if self.failures.len() == 1 { "" } else { "s" }
+            )
+            .yellow()
+            .bold()
+        );
+        for failure in &self.failures {
+            eprintln!("  {}: {:#}", failure.source.bold(), failure.error);
+        }
+        eprintln!();
+
+        Ok(LoadStatus::Partial)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failure(source: &str) -> Failure {
+        Failure {
+            source: source.into(),
+            error: eyre!("401 Unauthorized"),
+        }
+    }
+
+    #[test]
+    fn every_course_failing_is_a_total_failure() {
+        let loaded = Loaded::from_courses(vec![Err(failure("a")), Err(failure("b"))]);
+        assert_eq!(loaded.successes, 0);
+        assert!(loaded.report_failures().is_err());
+    }
+
+    #[test]
+    fn some_courses_failing_is_partial() {
+        let loaded = Loaded::from_courses(vec![Ok(vec![]), Err(failure("b"))]);
+        assert_eq!(loaded.report_failures().unwrap(), LoadStatus::Partial);
+    }
+
+    #[test]
+    fn no_courses_is_a_success() {
+        let mut loaded = Loaded::from_courses(vec![]);
+        loaded.failures.push(failure("Gradescope"));
+        assert_eq!(loaded.report_failures().unwrap(), LoadStatus::Partial);
+    }
+}
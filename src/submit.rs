@@ -0,0 +1,414 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local};
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result, Section,
+};
+use colored::Colorize;
+use futures::future::try_join_all;
+use lazy_static::lazy_static;
+use reqwest::{
+    header::LOCATION,
+    multipart::{Form, Part},
+    redirect, Method, Response, Url,
+};
+use serde_json::{json, Value};
+use tokio::{fs, io::AsyncReadExt};
+
+use crate::{
+    cache,
+    canvas::{assignment_url, assignments_url, lookup_assignment},
+    canvas_api::{Attachment, CanvasAssignment, CanvasCourse, Submission, UploadSlot},
+    canvas_url, config, decode_json,
+    editor::{compose, quote},
+    fetch_fresh, format_datetime, html,
+    progress::Progress,
+    send_json,
+};
+
+lazy_static! {
+    // The upload step answers with a redirect that has to be followed with credentials, which
+    // reqwest would drop if it followed it on its own.
+    static ref UPLOAD_CLIENT: reqwest::Client = reqwest::Client::builder()
+        .redirect(redirect::Policy::none())
+        .build()
+        .unwrap();
+}
+
+/// Fails unless the assignment currently accepts a submission of the given type.
+pub fn check_submittable(assignment: &CanvasAssignment, submission_type: &str) -> Result<()> {
+    if assignment.locked_for_user {
+        return Err(eyre!("{} is locked", assignment.name.trim())).suggestion(
+            match (assignment.unlock_at, assignment.lock_at) {
+                (Some(unlock_at), _) if unlock_at > Local::now() => {
+                    format!("It unlocks {}", format_datetime(unlock_at))
+                }
+                _ => "It may have closed, or depend on another module being completed".into(),
+            },
+        );
+    }
+
+    if !assignment
+        .submission_types
+        .iter()
+        .any(|x| x == submission_type)
+    {
+        return Err(eyre!(
+            "{} does not accept this kind of submission",
+            assignment.name.trim()
+        ))
+        .suggestion(format!(
+            "It accepts: {}",
+            assignment.submission_types.join(", ")
+        ));
+    }
+
+    let attempts = assignment
+        .submission
+        .as_ref()
+        .and_then(|x| x.attempt)
+        .unwrap_or(0);
+    if let Some(allowed) = assignment.allowed_attempts.filter(|x| *x > 0) {
+        if attempts >= allowed {
+            return Err(eyre!(
+                "All {} attempts on {} have been used",
+                allowed,
+                assignment.name.trim()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Sends the assignment's submission, then forgets the cached copies of the assignment so it
+/// shows up.
+pub async fn create_submission(
+    config: &config::Config,
+    assignment: &CanvasAssignment,
+    submission: Value,
+) -> Result<Submission> {
+    let submission = send_json(
+        config,
+        Method::POST,
+        &format!(
+            "/api/v1/courses/{}/assignments/{}/submissions",
+            assignment.course_id, assignment.id
+        ),
+        &json!({ "submission": submission }),
+    )
+    .await
+    .wrap_err("Unable to create submission")?;
+
+    cache::invalidate(&canvas_url(config, &assignments_url(assignment.course_id))).await;
+    cache::invalidate(&canvas_url(
+        config,
+        &assignment_url(assignment.course_id, assignment.id),
+    ))
+    .await;
+
+    Ok(submission)
+}
+
+/// Uploads a file for the assignment, using Canvas's three-step upload.
+async fn upload_file(
+    config: &config::Config,
+    assignment: &CanvasAssignment,
+    path: &Path,
+) -> Result<Attachment> {
+    let data = fs::read(path)
+        .await
+        .wrap_err_with(|| eyre!("Unable to read {}", path.display()))?;
+    let name = path
+        .file_name()
+        .map(|x| x.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "upload".into());
+
+    // 1. Tell Canvas about the file, and get a place to send it.
+    let slot: UploadSlot = send_json(
+        config,
+        Method::POST,
+        &format!(
+            "/api/v1/courses/{}/assignments/{}/submissions/self/files",
+            assignment.course_id, assignment.id
+        ),
+        &json!({ "name": name, "size": data.len() }),
+    )
+    .await
+    .wrap_err_with(|| eyre!("Unable to start uploading {}", name))?;
+
+    // 2. Send the file along with the parameters Canvas asked for, which must come first.
+    let mut form = Form::new();
+    for (key, value) in slot.upload_params {
+        let value = match value {
+            Value::String(s) => s,
+            Value::Null => continue,
+            other => other.to_string(),
+        };
+        form = form.text(key, value);
+    }
+    form = form.part(
+        slot.file_param.unwrap_or_else(|| "file".into()),
+        Part::bytes(data).file_name(name.clone()),
+    );
+
+    let response = UPLOAD_CLIENT
+        .post(&slot.upload_url)
+        .multipart(form)
+        .send()
+        .await
+        .wrap_err_with(|| eyre!("Unable to upload {}", name))?;
+
+    // 3. Confirm the upload, which may take a redirect.
+    let response = if response.status().is_redirection() {
+        let location = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|x| x.to_str().ok())
+            .ok_or_else(|| eyre!("Upload of {} redirected nowhere", name))?;
+        let location = Url::parse(&slot.upload_url)?.join(location)?;
+        UPLOAD_CLIENT
+            .get(location)
+            .header("Authorization", format!("Bearer {}", config.token))
+            .send()
+            .await
+            .wrap_err_with(|| eyre!("Unable to confirm upload of {}", name))?
+    } else {
+        response
+    };
+
+    let body = checked(response)
+        .await
+        .wrap_err_with(|| eyre!("Unable to upload {}", name))?;
+    decode_json(config, &body).wrap_err_with(|| eyre!("Unable to parse upload of {}", name))
+}
+
+async fn checked(response: Response) -> Result<Vec<u8>> {
+    let response = response
+        .error_for_status()
+        .wrap_err("Server returned error")?;
+    Ok(response
+        .bytes()
+        .await
+        .wrap_err("Failed to read data from server")?
+        .to_vec())
+}
+
+/// What to turn in.
+pub enum Content {
+    Files(Vec<PathBuf>),
+    /// Markdown read from a file, or from stdin when the path is `-`.
+    Text(PathBuf),
+    Url(String),
+    /// Markdown written in `$EDITOR`.
+    Editor,
+}
+
+pub fn markdown_to_html(markdown: &str) -> String {
+    let parser = pulldown_cmark::Parser::new_ext(markdown, pulldown_cmark::Options::all());
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, parser);
+    html
+}
+
+async fn read_text(path: &Path) -> Result<String> {
+    if path == Path::new("-") {
+        let mut text = String::new();
+        tokio::io::stdin()
+            .read_to_string(&mut text)
+            .await
+            .wrap_err("Unable to read from stdin")?;
+        Ok(text)
+    } else {
+        fs::read_to_string(path)
+            .await
+            .wrap_err_with(|| eyre!("Unable to read {}", path.display()))
+    }
+}
+
+/// The assignment's details, quoted below the editor template.
+fn editor_context(course: &CanvasCourse, assignment: &CanvasAssignment) -> String {
+    let mut context = format!("#\n# {} ({})\n", assignment.name.trim(), course.name);
+    if let Some(due_at) = assignment.due_at {
+        context += &format!("# Due {}\n", format_datetime(due_at));
+    }
+    if let Some(description) = &assignment.description {
+        context += "#\n";
+        context += &quote(&html::render_plain(description));
+        context += "\n";
+    }
+    context
+}
+
+async fn upload_files(
+    config: &config::Config,
+    assignment: &CanvasAssignment,
+    files: &[PathBuf],
+) -> Result<Submission> {
+    for file in files {
+        if !file.is_file() {
+            return Err(eyre!("{} is not a file", file.display()));
+        }
+    }
+
+    let progress = Progress::new();
+    let attachments = {
+        let progress = &progress;
+        try_join_all(files.iter().map(|file| async move {
+            progress
+                .wrap(
+                    &format!("Uploading {}", file.display()),
+                    upload_file(config, assignment, file),
+                )
+                .await
+        }))
+        .await
+    };
+    let submission = match attachments {
+        Ok(attachments) => {
+            progress
+                .wrap(
+                    "Submitting",
+                    create_submission(
+                        config,
+                        assignment,
+                        json!({
+                            "submission_type": "online_upload",
+                            "file_ids": attachments.iter().map(|x| x.id).collect::<Vec<_>>(),
+                        }),
+                    ),
+                )
+                .await
+        }
+        Err(error) => Err(error),
+    };
+    progress.finish();
+    submission
+}
+
+pub async fn run_submit(config: &config::Config, query: &str, content: Content) -> Result<()> {
+    let (course, assignment) = lookup_assignment(config, query).await?;
+    // The attempt count and lock state decide whether submitting is allowed, so a cached copy
+    // will not do.
+    let assignment: CanvasAssignment =
+        fetch_fresh(config, &assignment_url(course.id, assignment.id)).await?;
+
+    let submission = match content {
+        Content::Files(files) => {
+            check_submittable(&assignment, "online_upload")?;
+            upload_files(config, &assignment, &files).await?
+        }
+        Content::Url(url) => {
+            check_submittable(&assignment, "online_url")?;
+            Url::parse(&url).wrap_err_with(|| eyre!("`{}` is not a valid URL", url))?;
+            create_submission(
+                config,
+                &assignment,
+                json!({ "submission_type": "online_url", "url": url }),
+            )
+            .await?
+        }
+        Content::Text(path) => {
+            check_submittable(&assignment, "online_text_entry")?;
+            let text = read_text(&path).await?;
+            if text.trim().is_empty() {
+                return Err(eyre!("Refusing to submit an empty text entry"));
+            }
+            create_submission(
+                config,
+                &assignment,
+                json!({ "submission_type": "online_text_entry", "body": markdown_to_html(&text) }),
+            )
+            .await?
+        }
+        Content::Editor => {
+            check_submittable(&assignment, "online_text_entry")?;
+            let text = compose(
+                &format!("submission-{}", assignment.id),
+                "",
+                &editor_context(&course, &assignment),
+            )
+            .await?;
+            create_submission(
+                config,
+                &assignment,
+                json!({ "submission_type": "online_text_entry", "body": markdown_to_html(&text) }),
+            )
+            .await?
+        }
+    };
+
+    print_receipt(&course, &assignment, &submission);
+
+    Ok(())
+}
+
+fn print_receipt(course: &CanvasCourse, assignment: &CanvasAssignment, submission: &Submission) {
+    println!(
+        "{}",
+        format!("Submitted {} ({})", assignment.name.trim(), course.name).bold()
+    );
+    if let Some(attempt) = submission.attempt {
+        println!("  Attempt: {}", attempt);
+    }
+    if let Some(submitted_at) = &submission.submitted_at {
+        match DateTime::parse_from_rfc3339(submitted_at) {
+            Ok(x) => println!("  Submitted: {}", format_datetime(x.with_timezone(&Local))),
+            Err(_) => println!("  Submitted: {}", submitted_at),
+        }
+    }
+    if submission.late {
+        println!("  {}", "Late".red().bold());
+    }
+    if let Some(url) = &submission.preview_url {
+        println!("  {}", url);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+    use crate::{config::test_config, fetch};
+
+    #[tokio::test]
+    async fn submitting_forgets_the_cached_assignment() {
+        let server = MockServer::start().await;
+        let assignment = CanvasAssignment {
+            id: 4,
+            name: "Homework 1".into(),
+            course_id: 61,
+            ..Default::default()
+        };
+        Mock::given(method("GET"))
+            .and(path("/api/v1/courses/61/assignments/4"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&assignment))
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/v1/courses/61/assignments/4/submissions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "attempt": 1 })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let mut config = test_config(&server.uri());
+        config.cache_ttl_secs = 3600;
+
+        let url = assignment_url(61, 4);
+        let _: CanvasAssignment = fetch(&config, &url).await.unwrap();
+        create_submission(
+            &config,
+            &assignment,
+            json!({ "submission_type": "online_url" }),
+        )
+        .await
+        .unwrap();
+        let _: CanvasAssignment = fetch(&config, &url).await.unwrap();
+    }
+}